@@ -0,0 +1,11 @@
+// Comparator schedule for batcher8: 8 lanes, 6 cycles.
+// Lane N uses bits [N*4 +: 4] = {min_flag, partner[2:0]}.
+reg [31:0] batcher8_rom [0:5];
+initial begin
+    batcher8_rom[0] = {4'd6, 4'd15, 4'd4, 4'd13, 4'd2, 4'd11, 4'd0, 4'd9};
+    batcher8_rom[1] = {4'd5, 4'd4, 4'd15, 4'd14, 4'd1, 4'd0, 4'd11, 4'd10};
+    batcher8_rom[2] = {4'd7, 4'd5, 4'd14, 4'd4, 4'd3, 4'd1, 4'd10, 4'd0};
+    batcher8_rom[3] = {4'd3, 4'd2, 4'd1, 4'd0, 4'd15, 4'd14, 4'd13, 4'd12};
+    batcher8_rom[4] = {4'd7, 4'd6, 4'd3, 4'd2, 4'd13, 4'd12, 4'd1, 4'd0};
+    batcher8_rom[5] = {4'd7, 4'd5, 4'd14, 4'd3, 4'd12, 4'd1, 4'd10, 4'd0};
+end
@@ -0,0 +1,141 @@
+// Sorting network bitonic8a: 8 lanes, 6 stages.
+module bitonic8a (
+    input  wire [127:0] in_data,
+    output wire [127:0] out_data
+);
+    wire [15:0] s0_0 = in_data[0*16 +: 16];
+    wire [15:0] s0_1 = in_data[1*16 +: 16];
+    wire [15:0] s0_2 = in_data[2*16 +: 16];
+    wire [15:0] s0_3 = in_data[3*16 +: 16];
+    wire [15:0] s0_4 = in_data[4*16 +: 16];
+    wire [15:0] s0_5 = in_data[5*16 +: 16];
+    wire [15:0] s0_6 = in_data[6*16 +: 16];
+    wire [15:0] s0_7 = in_data[7*16 +: 16];
+    wire [15:0] s1_0;
+    wire [15:0] s1_1;
+    wire [15:0] s1_2;
+    wire [15:0] s1_3;
+    wire [15:0] s1_4;
+    wire [15:0] s1_5;
+    wire [15:0] s1_6;
+    wire [15:0] s1_7;
+    assign s1_0 = (s0_0 <= s0_1) ? s0_0 : s0_1;
+    assign s1_1 = (s0_0 <= s0_1) ? s0_1 : s0_0;
+    assign s1_3 = (s0_3 <= s0_2) ? s0_3 : s0_2;
+    assign s1_2 = (s0_3 <= s0_2) ? s0_2 : s0_3;
+    assign s1_4 = (s0_4 <= s0_5) ? s0_4 : s0_5;
+    assign s1_5 = (s0_4 <= s0_5) ? s0_5 : s0_4;
+    assign s1_7 = (s0_7 <= s0_6) ? s0_7 : s0_6;
+    assign s1_6 = (s0_7 <= s0_6) ? s0_6 : s0_7;
+    wire [15:0] s2_0;
+    wire [15:0] s2_1;
+    wire [15:0] s2_2;
+    wire [15:0] s2_3;
+    wire [15:0] s2_4;
+    wire [15:0] s2_5;
+    wire [15:0] s2_6;
+    wire [15:0] s2_7;
+    assign s2_0 = (s1_0 <= s1_2) ? s1_0 : s1_2;
+    assign s2_2 = (s1_0 <= s1_2) ? s1_2 : s1_0;
+    assign s2_1 = (s1_1 <= s1_3) ? s1_1 : s1_3;
+    assign s2_3 = (s1_1 <= s1_3) ? s1_3 : s1_1;
+    assign s2_7 = (s1_7 <= s1_5) ? s1_7 : s1_5;
+    assign s2_5 = (s1_7 <= s1_5) ? s1_5 : s1_7;
+    assign s2_6 = (s1_6 <= s1_4) ? s1_6 : s1_4;
+    assign s2_4 = (s1_6 <= s1_4) ? s1_4 : s1_6;
+    wire [15:0] s3_0;
+    wire [15:0] s3_1;
+    wire [15:0] s3_2;
+    wire [15:0] s3_3;
+    wire [15:0] s3_4;
+    wire [15:0] s3_5;
+    wire [15:0] s3_6;
+    wire [15:0] s3_7;
+    assign s3_0 = (s2_0 <= s2_1) ? s2_0 : s2_1;
+    assign s3_1 = (s2_0 <= s2_1) ? s2_1 : s2_0;
+    assign s3_2 = (s2_2 <= s2_3) ? s2_2 : s2_3;
+    assign s3_3 = (s2_2 <= s2_3) ? s2_3 : s2_2;
+    assign s3_5 = (s2_5 <= s2_4) ? s2_5 : s2_4;
+    assign s3_4 = (s2_5 <= s2_4) ? s2_4 : s2_5;
+    assign s3_7 = (s2_7 <= s2_6) ? s2_7 : s2_6;
+    assign s3_6 = (s2_7 <= s2_6) ? s2_6 : s2_7;
+    wire [15:0] s4_0;
+    wire [15:0] s4_1;
+    wire [15:0] s4_2;
+    wire [15:0] s4_3;
+    wire [15:0] s4_4;
+    wire [15:0] s4_5;
+    wire [15:0] s4_6;
+    wire [15:0] s4_7;
+    assign s4_0 = (s3_0 <= s3_4) ? s3_0 : s3_4;
+    assign s4_4 = (s3_0 <= s3_4) ? s3_4 : s3_0;
+    assign s4_1 = (s3_1 <= s3_5) ? s3_1 : s3_5;
+    assign s4_5 = (s3_1 <= s3_5) ? s3_5 : s3_1;
+    assign s4_2 = (s3_2 <= s3_6) ? s3_2 : s3_6;
+    assign s4_6 = (s3_2 <= s3_6) ? s3_6 : s3_2;
+    assign s4_3 = (s3_3 <= s3_7) ? s3_3 : s3_7;
+    assign s4_7 = (s3_3 <= s3_7) ? s3_7 : s3_3;
+    wire [15:0] s5_0;
+    wire [15:0] s5_1;
+    wire [15:0] s5_2;
+    wire [15:0] s5_3;
+    wire [15:0] s5_4;
+    wire [15:0] s5_5;
+    wire [15:0] s5_6;
+    wire [15:0] s5_7;
+    assign s5_0 = (s4_0 <= s4_2) ? s4_0 : s4_2;
+    assign s5_2 = (s4_0 <= s4_2) ? s4_2 : s4_0;
+    assign s5_1 = (s4_1 <= s4_3) ? s4_1 : s4_3;
+    assign s5_3 = (s4_1 <= s4_3) ? s4_3 : s4_1;
+    assign s5_4 = (s4_4 <= s4_6) ? s4_4 : s4_6;
+    assign s5_6 = (s4_4 <= s4_6) ? s4_6 : s4_4;
+    assign s5_5 = (s4_5 <= s4_7) ? s4_5 : s4_7;
+    assign s5_7 = (s4_5 <= s4_7) ? s4_7 : s4_5;
+    wire [15:0] s6_0;
+    wire [15:0] s6_1;
+    wire [15:0] s6_2;
+    wire [15:0] s6_3;
+    wire [15:0] s6_4;
+    wire [15:0] s6_5;
+    wire [15:0] s6_6;
+    wire [15:0] s6_7;
+    assign s6_0 = (s5_0 <= s5_1) ? s5_0 : s5_1;
+    assign s6_1 = (s5_0 <= s5_1) ? s5_1 : s5_0;
+    assign s6_2 = (s5_2 <= s5_3) ? s5_2 : s5_3;
+    assign s6_3 = (s5_2 <= s5_3) ? s5_3 : s5_2;
+    assign s6_4 = (s5_4 <= s5_5) ? s5_4 : s5_5;
+    assign s6_5 = (s5_4 <= s5_5) ? s5_5 : s5_4;
+    assign s6_6 = (s5_6 <= s5_7) ? s5_6 : s5_7;
+    assign s6_7 = (s5_6 <= s5_7) ? s5_7 : s5_6;
+    assign out_data[0*16 +: 16] = s6_0;
+    assign out_data[1*16 +: 16] = s6_1;
+    assign out_data[2*16 +: 16] = s6_2;
+    assign out_data[3*16 +: 16] = s6_3;
+    assign out_data[4*16 +: 16] = s6_4;
+    assign out_data[5*16 +: 16] = s6_5;
+    assign out_data[6*16 +: 16] = s6_6;
+    assign out_data[7*16 +: 16] = s6_7;
+endmodule
+// Self-checking testbench for bitonic8a.
+module bitonic8a_tb;
+    reg  [127:0] in_data;
+    wire [127:0] out_data;
+    integer mask, lane, errors;
+    bitonic8a dut (.in_data(in_data), .out_data(out_data));
+    initial begin
+        errors = 0;
+        for (mask = 0; mask < 256; mask = mask + 1) begin
+            for (lane = 0; lane < 8; lane = lane + 1)
+                in_data[lane*16 +: 16] = (mask >> lane) & 1;
+            #1;
+            for (lane = 1; lane < 8; lane = lane + 1)
+                if (out_data[(lane-1)*16 +: 16] > out_data[lane*16 +: 16]) begin
+                    $display("FAIL: mask %0d, lane %0d", mask, lane);
+                    errors = errors + 1;
+                end
+        end
+        if (errors == 0) $display("PASS");
+        else $display("FAIL: %0d errors", errors);
+        $finish;
+    end
+endmodule
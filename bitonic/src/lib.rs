@@ -0,0 +1,3107 @@
+//! Testing bitonic sorting networks for stability
+//! Copyright 2021 by Alex Utter
+//!
+//! This file defines a series of bitonic sorting networks, tests whether
+//! they function correctly, and then tests whether their order remains
+//! stable for tiebreaking purposes.
+//!
+//! The motivation is an FPGA design problem described here:
+//! https://www.reddit.com/r/FPGA/comments/qe9j6s/vectorpacking_algorithm/
+//!
+//! The networks and the functions that build, test, and export them are
+//! provided as a library, e.g., for use of sort_slice() or the constant
+//! stage tables by other crates; main.rs provides the command-line tool.
+
+// Coding style for this file prefers explicit returns and struct-literal
+// construction; silence the corresponding lints.
+#![allow(clippy::needless_return)]
+#![allow(clippy::init_numbered_fields)]
+#![allow(clippy::clone_on_copy)]
+#![allow(clippy::ptr_arg)]
+
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::Write;
+use std::time::Instant;
+
+// Parameters for creating a new Lane or LaneArray object
+// (i.e., Options for how to initialize the key-values for sorting.)
+pub enum LaneArrayType {
+    Simple(u64),    // Key includes keep/discard mask and lane index
+    Hidden(u64),    // Key includes keep/discard mask only
+    MaxValue(u64),  // As Simple, but disabled lanes use the maximum key
+    Random(u64),    // As Simple, but disabled lanes use a random large key
+}
+
+// An encoding strategy maps a keep/discard mask to a LaneArrayType.
+// (e.g., the LaneArrayType::Simple constructor.)
+pub type EncodingStrategy = fn(u64) -> LaneArrayType;
+
+impl LaneArrayType {
+    // Look up an encoding strategy by name.
+    pub fn parse(name:&str) -> Option<EncodingStrategy> {
+        match name {
+            "simple" => Some(LaneArrayType::Simple),
+            "hidden" => Some(LaneArrayType::Hidden),
+            "max"    => Some(LaneArrayType::MaxValue),
+            "random" => Some(LaneArrayType::Random),
+            _ => None,
+        }
+    }
+}
+
+// Each "lane" has a key-value (for sorting) and a payload that follows
+// the key through the network.  By default, the payload is metadata (for
+// verification), but it can be any record associated with the key.
+#[derive(Clone, Copy)]
+pub struct Lane<P = u64> {
+    pub key: u64,
+    pub payload: P,
+}
+
+// Use a large penalty to increment the keys of disabled lanes.
+pub const PENALTY:u64 = 256;
+
+// Number of low-order key bits reserved by LaneArray::with_meta_tiebreak().
+pub const META_BITS:u32 = 16;
+
+impl Lane {
+    // Create a key-value pair based on an index and mask.
+    // Note: Verification data in unused lanes is "don't-care".
+    pub fn new(typ:&LaneArrayType, idx:u8) -> Lane {
+        let chk = 1u64 << idx;
+        let idx64 = idx as u64;
+        match typ {
+            LaneArrayType::Simple(mask) => {
+                let pen = if mask & chk > 0 {PENALTY} else {0};
+                Lane {key: cmp::max(idx64,pen), payload: cmp::max(idx64,pen)}},
+            LaneArrayType::Hidden(mask) => {
+                let pen = if mask & chk > 0 {PENALTY} else {0};
+                Lane {key: pen, payload: cmp::max(idx64,pen)}},
+            LaneArrayType::MaxValue(mask) => {
+                let pen = if mask & chk > 0 {PENALTY} else {0};
+                let key = if mask & chk > 0 {u64::MAX} else {idx64};
+                Lane {key, payload: cmp::max(idx64,pen)}},
+            LaneArrayType::Random(mask) => {
+                let pen = if mask & chk > 0 {PENALTY} else {0};
+                let rnd = Rng::new((mask << 8) | idx64).next() % PENALTY;
+                let key = if mask & chk > 0 {PENALTY + rnd} else {idx64};
+                Lane {key, payload: cmp::max(idx64,pen)}},
+        }
+    }
+}
+
+// A comparison predicate for lane-swap operations: returns true if
+// the two lanes are already in order (i.e., "a" may stay in the first
+// index and "b" in the second), false if they should be swapped.
+pub trait Comparator<P = u64> {
+    fn order(a:&Lane<P>, b:&Lane<P>) -> bool;
+}
+
+// Default ordering: ascending by key, ties keep their original order.
+pub struct Ascending;
+
+impl<P> Comparator<P> for Ascending {
+    fn order(a:&Lane<P>, b:&Lane<P>) -> bool {a.key <= b.key}
+}
+
+// Each lane-swap operation either compares and swaps its inputs,
+// shifts valid inputs toward the first index (see LaneArray::shift),
+// or unconditionally exchanges its inputs (i.e., fixed wiring).
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Swap,
+    Shift,
+    Exchange,
+}
+
+// Alternate ordering: ascending by key, ties exchange their inputs.
+pub struct StrictAscending;
+
+impl<P> Comparator<P> for StrictAscending {
+    fn order(a:&Lane<P>, b:&Lane<P>) -> bool {a.key < b.key}
+}
+
+// Runtime tie-break policy for lane-swap operations with equal keys.
+// LowerWins keeps both inputs in place (the default, and the behavior of
+// swap() and apply()); HigherWins exchanges them, as some hardware does.
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum TieBreak {
+    #[default]
+    LowerWins,
+    HigherWins,
+}
+
+// A lane-swap operation is a pair of input/output indices.
+// Order is preserved if #1.key <= #2.key, otherwise swap.
+// The smaller key is always written to #1 and the larger key to #2,
+// regardless of which index is lower (e.g., sw(3,2) is descending).
+#[derive(Clone, Copy, PartialEq)]
+pub struct LaneSwap(pub usize, pub usize, pub Mode);
+
+impl LaneSwap {
+    // Checked constructor: reject a self-comparison, which is always
+    // a no-op and usually a typo.  (See also sw(), which is unchecked.)
+    pub fn new(a:usize, b:usize) -> Result<LaneSwap, &'static str> {
+        if a == b {
+            Err("Lane-swap compares a lane to itself.")
+        } else {
+            Ok(sw(a, b))
+        }
+    }
+
+    // Output lane that receives the smaller key.
+    pub fn min_out(&self) -> usize {self.0}
+
+    // Output lane that receives the larger key.
+    pub fn max_out(&self) -> usize {self.1}
+
+    // Does this operation exchange its inputs, given the lanes bound for
+    // min_out() and max_out() respectively?  Swaps compare keys using the
+    // designated predicate, shifts move up a valid lane (i.e., if the lane
+    // bound for min_out() is invalid), and fixed exchanges always do.
+    pub fn swaps<C:Comparator<P>, P>(&self, a:&Lane<P>, b:&Lane<P>) -> bool {
+        match self.2 {
+            Mode::Swap  => !C::order(a, b),
+            Mode::Shift => a.key >= PENALTY,
+            Mode::Exchange => true,
+        }
+    }
+}
+
+pub fn sw(a:usize, b:usize) -> LaneSwap {
+    LaneSwap {0:a, 1:b, 2:Mode::Swap}
+}
+
+// As sw(), but for an information-deleting shift operation.
+pub fn sh(a:usize, b:usize) -> LaneSwap {
+    LaneSwap {0:a, 1:b, 2:Mode::Shift}
+}
+
+// As sw(), but for a fixed exchange that ignores the keys.
+pub fn ex(a:usize, b:usize) -> LaneSwap {
+    LaneSwap {0:a, 1:b, 2:Mode::Exchange}
+}
+
+// An array of lane values, which can be used as an input vector,
+// the state of a pipeline stage, or a vector of outputs.
+#[derive(Clone)]
+pub struct LaneArray<P = u64> {
+    pub lanes: Vec<Lane<P>>,
+}
+
+impl LaneArray {
+    // Create a new vector of Lanes of the designated size and type.
+    pub fn new(len:u8, typ:&LaneArrayType) -> LaneArray {
+        LaneArray {lanes: (0..len).map(|n| Lane::new(typ, n)).collect()}
+    }
+
+    // Create a new vector of Lanes with the designated keys.
+    // Metadata is set to the lane index, so that order can be verified.
+    pub fn from_keys(keys:&[u64]) -> LaneArray {
+        LaneArray {lanes: keys.iter().enumerate()
+            .map(|(n, key)| Lane {key: *key, payload: n as u64}).collect()}
+    }
+
+    // List the key-value for each lane.
+    pub fn keys(&self) -> Vec<u64> {
+        self.lanes.iter().map(|lane| lane.key).collect()
+    }
+
+    // Are all lanes sorted in ascending order by key?
+    pub fn is_sorted_key(&self) -> bool {
+        let mut prev = 0u64;
+        for lane in self.lanes.iter() {
+            if lane.key < prev {return false} else {prev = lane.key}
+        }
+        return true
+    }
+
+    // As is_sorted_key(), but every key at or above PENALTY (i.e., each
+    // disabled lane, however it is encoded) sorts as equal, since the
+    // order of disabled lanes and their placeholder keys is don't-care.
+    pub fn is_sorted_valid(&self) -> bool {
+        self.lanes.windows(2).all(|w| cmp::min(w[0].key, PENALTY) <= cmp::min(w[1].key, PENALTY))
+    }
+
+    // As shift(), but assert that every valid input lane (i.e., with key
+    // below PENALTY) appears in the output exactly once, by metadata.
+    pub fn shift_checked(&self, ops:&Vec<LaneSwap>) -> LaneArray {
+        let result = self.shift(ops);
+        for a in self.lanes.iter().filter(|lane| lane.key < PENALTY) {
+            let count = result.lanes.iter()
+                .filter(|b| b.key < PENALTY && b.payload == a.payload).count();
+            assert_eq!(count, 1, "shift() lost or duplicated lane {}", a.payload);
+        }
+        return result
+    }
+
+    // Count the pairs of lanes whose keys are out of order, i.e., zero
+    // if sorted, up to n*(n-1)/2 if sorted in reverse.
+    pub fn inversions(&self) -> usize {
+        let lanes = &self.lanes;
+        (0..lanes.len()).map(|n| lanes[n+1..].iter()
+            .filter(|b| lanes[n].key > b.key).count()).sum()
+    }
+
+    // Count the pairs of lanes with equal keys whose metadata is out of
+    // order, i.e., the stability violations in a sorted output.
+    pub fn meta_inversions(&self) -> usize {
+        let lanes = &self.lanes;
+        (0..lanes.len()).map(|n| lanes[n+1..].iter()
+            .filter(|b| lanes[n].key == b.key && lanes[n].payload > b.payload).count()).sum()
+    }
+
+    // Do the keys rise then fall, or form a rotation of such a sequence?
+    // This is the precondition for a bitonic merge.
+    pub fn is_bitonic(&self) -> bool {
+        let keys = self.keys();
+        let dirs: Vec<bool> = (0..keys.len())
+            .map(|n| (keys[n], keys[(n + 1) % keys.len()]))
+            .filter(|(a, b)| a != b).map(|(a, b)| a < b).collect();
+        let changes = (0..dirs.len()).filter(|n| dirs[*n] != dirs[(n + 1) % dirs.len()]).count();
+        return changes <= 2
+    }
+
+    // Are all lanes sorted in ascending order by metadata?
+    pub fn is_sorted_meta(&self) -> bool {
+        let mut prev = 0u64;
+        for lane in self.lanes.iter() {
+            if lane.payload < prev {return false} else {prev = lane.payload}
+        }
+        return true
+    }
+
+    // Are all lanes sorted in ascending order by key, then by metadata?
+    pub fn is_sorted_key_then_meta(&self) -> bool {
+        self.lanes.windows(2).all(|w| (w[0].key, w[0].payload) <= (w[1].key, w[1].payload))
+    }
+
+    // Append metadata to each key as low-order bits, so that sorting by
+    // the new key also resolves ties by metadata.  Keys must fit in the
+    // remaining high-order bits; otherwise, use the KeyThenMeta ordering.
+    pub fn with_meta_tiebreak(&self) -> LaneArray {
+        let mut result = self.clone();
+        for lane in result.lanes.iter_mut() {
+            assert!(lane.key < 1u64 << (64 - META_BITS), "Key too large for tiebreak: {}", lane.key);
+            assert!(lane.payload < 1u64 << META_BITS);
+            lane.key = (lane.key << META_BITS) | lane.payload;
+        }
+        return result
+    }
+
+    // Inverse of with_meta_tiebreak(), restoring the original keys.
+    pub fn without_meta_tiebreak(&self) -> LaneArray {
+        let mut result = self.clone();
+        for lane in result.lanes.iter_mut() {
+            lane.key >>= META_BITS;
+        }
+        return result
+    }
+
+    // Do all lanes with equal keys remain in their original order?
+    pub fn is_stable(&self) -> bool {
+        for (n, a) in self.lanes.iter().enumerate() {
+            for b in self.lanes[n+1..].iter() {
+                if a.key == b.key && a.payload > b.payload {return false}
+            }
+        }
+        return true
+    }
+}
+
+impl<P:Clone> LaneArray<P> {
+    // Create a new vector of Lanes with the designated keys and payloads.
+    pub fn from_records(records:Vec<(u64, P)>) -> LaneArray<P> {
+        LaneArray {lanes: records.into_iter()
+            .map(|(key, payload)| Lane {key, payload}).collect()}
+    }
+
+    // Apply a series of lane-swap operations to generate a new LaneArray.
+    // Each operator is a pair of input/output indices; smaller key copied
+    // to the first index, larger key to the second.
+    pub fn swap(&self, ops:&Vec<LaneSwap>) -> LaneArray<P> {
+        self.swap_by::<Ascending>(ops, 1)
+    }
+
+    // As swap(), but using the designated tie-break policy.
+    pub fn swap_with(&self, ops:&Vec<LaneSwap>, tie:TieBreak) -> LaneArray<P> {
+        match tie {
+            TieBreak::LowerWins  => self.swap_by::<Ascending>(ops, 1),
+            TieBreak::HigherWins => self.swap_by::<StrictAscending>(ops, 1),
+        }
+    }
+
+    // As swap(), but using the designated comparison predicate.
+    // Lanes are handled in groups of contiguous lanes, where each
+    // operator indexes a group and compares the first lane in each.
+    // (Group size 1 is the usual case, with one lane per index.)
+    // Operators in Mode::Shift behave as described in shift(), and
+    // operators in Mode::Exchange always exchange their inputs.
+    pub fn swap_by<C:Comparator<P>>(&self, ops:&Vec<LaneSwap>, group:usize) -> LaneArray<P> {
+        let mut result = self.clone();
+        for op in ops.iter() {
+            let (g1, g2) = (op.min_out() * group, op.max_out() * group);
+            let keep = !op.swaps::<C, P>(&self.lanes[g1], &self.lanes[g2]);
+            for k in 0..group {
+                if keep {
+                    result.lanes[g1+k] = self.lanes[g1+k].clone();
+                    result.lanes[g2+k] = self.lanes[g2+k].clone();
+                } else if op.2 != Mode::Shift {
+                    result.lanes[g1+k] = self.lanes[g2+k].clone();
+                    result.lanes[g2+k] = self.lanes[g1+k].clone();
+                } else {
+                    result.lanes[g1+k] = self.lanes[g2+k].clone();
+                    result.lanes[g2+k] = Lane {key:PENALTY, payload:self.lanes[g1+k].payload.clone()};
+                }
+            }
+        }
+        return result
+    }
+
+    // Information-deleting analogue to swap() function, shifts up
+    // by replacing any invalid inputs with a constant placeholder.
+    // (The placeholder keeps the payload of the discarded lane.)
+    // Every operator is treated as Mode::Shift, regardless of its tag.
+    pub fn shift(&self, ops:&Vec<LaneSwap>) -> LaneArray<P> {
+        let ops = ops.iter().map(|op| sh(op.0, op.1)).collect();
+        self.swap_by::<Ascending>(&ops, 1)
+    }
+}
+
+impl fmt::Display for LaneArray {
+    // Print the key values for all lanes.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "(")?;
+        for (n, lane) in self.lanes.iter().enumerate() {
+            if n > 0 {write!(f, ", ")?;}
+            write!(f, "{}", &lane.key)?;
+        }
+        write!(f, ")")
+    }
+}
+
+// Error when a LaneArray does not match the size of a network.
+#[derive(Debug)]
+pub struct SizeError {
+    pub expected: usize,
+    pub actual: usize,
+}
+
+impl fmt::Display for SizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Expected {} lanes, got {}.", self.expected, self.actual)
+    }
+}
+
+// A sorting network is a series of stages, each consisting of lane-swap
+// operations that can be executed in parallel.
+#[derive(Clone, PartialEq)]
+pub struct Network {
+    pub len: u8,
+    pub stages: Vec<Vec<LaneSwap>>,
+}
+
+impl Network {
+    // Create a network from a list of stages.
+    pub fn new(len:u8, stages:Vec<Vec<LaneSwap>>) -> Network {
+        Network {len, stages}
+    }
+
+    // Create a network from a constant table.
+    pub fn from_table(len:u8, table:StageTable) -> Network {
+        Network::new(len, table.iter()
+            .map(|stage| stage.iter().map(|(a, b)| sw(*a as usize, *b as usize)).collect())
+            .collect())
+    }
+
+    // Apply each stage in order to generate a new LaneArray.
+    pub fn apply<P:Clone>(&self, p0:&LaneArray<P>) -> LaneArray<P> {
+        self.apply_by::<Ascending, P>(p0)
+    }
+
+    // As apply(), but using the designated tie-break policy.
+    pub fn apply_with<P:Clone>(&self, p0:&LaneArray<P>, tie:TieBreak) -> LaneArray<P> {
+        match tie {
+            TieBreak::LowerWins  => self.apply_by::<Ascending, P>(p0),
+            TieBreak::HigherWins => self.apply_by::<StrictAscending, P>(p0),
+        }
+    }
+
+    // As apply(), but return an error if the input is the wrong size.
+    pub fn try_apply(&self, p0:&LaneArray) -> Result<LaneArray, SizeError> {
+        if p0.lanes.len() == self.len as usize {
+            Ok(self.apply(p0))
+        } else {
+            Err(SizeError {expected: self.len as usize, actual: p0.lanes.len()})
+        }
+    }
+
+    // As apply(), but using the designated comparison predicate.
+    pub fn apply_by<C:Comparator<P>, P:Clone>(&self, p0:&LaneArray<P>) -> LaneArray<P> {
+        let mut p = p0.clone();
+        for stage in self.stages.iter() {
+            p = p.swap_by::<C>(stage, 1);
+        }
+        return p
+    }
+
+    // As apply(), but each index refers to a group of contiguous lanes.
+    pub fn apply_grouped(&self, p0:&LaneArray, group:usize) -> LaneArray {
+        let mut p = p0.clone();
+        for stage in self.stages.iter() {
+            p = p.swap_by::<Ascending>(stage, group);
+        }
+        return p
+    }
+
+    // As apply(), but return the intermediate state after every stage.
+    pub fn apply_traced(&self, p0:&LaneArray) -> Vec<LaneArray> {
+        let mut trace = Vec::new();
+        let mut p = p0.clone();
+        for stage in self.stages.iter() {
+            p = p.swap(stage);
+            trace.push(p.clone());
+        }
+        return trace
+    }
+
+    // Number of stages, i.e., the pipeline depth.
+    pub fn depth(&self) -> usize {
+        self.stages.len()
+    }
+
+    // Total number of lane-swap operations.
+    pub fn size(&self) -> usize {
+        self.stages.iter().map(|stage| stage.len()).sum()
+    }
+
+    // List the lanes that pass through a stage untouched.
+    pub fn idle_lanes(&self, stage:usize) -> Vec<usize> {
+        let ops = &self.stages[stage];
+        (0..self.len as usize)
+            .filter(|n| !ops.iter().any(|op| op.0 == *n || op.1 == *n))
+            .collect()
+    }
+
+    // Iterate over every lane-swap operation in execution order,
+    // along with the index of the stage that contains it.
+    pub fn comparators(&self) -> impl Iterator<Item = (usize, &LaneSwap)> {
+        self.stages.iter().enumerate()
+            .flat_map(|(k, stage)| stage.iter().map(move |op| (k, op)))
+    }
+
+    // Count the lane-swap operations that exchange their inputs.
+    pub fn count_swaps(&self, p0:&LaneArray) -> usize {
+        self.count_ops(p0, |op, a, b| op.swaps::<Ascending, u64>(a, b))
+    }
+
+    // Count the lane-swap operations whose inputs satisfy a predicate,
+    // given the lanes bound for min_out() and max_out() respectively.
+    pub fn count_ops(&self, p0:&LaneArray, pred:impl Fn(&LaneSwap, &Lane, &Lane)->bool) -> usize {
+        let mut count = 0usize;
+        let mut p = p0.clone();
+        for stage in self.stages.iter() {
+            count += stage.iter()
+                .filter(|op| pred(op, &p.lanes[op.min_out()], &p.lanes[op.max_out()]))
+                .count();
+            p = p.swap(stage);
+        }
+        return count
+    }
+}
+
+// Incremental constructor for a Network, one lane-swap at a time.
+// A new stage is started automatically whenever a lane is reused.
+pub struct NetworkBuilder {
+    len: u8,
+    stages: Vec<Vec<LaneSwap>>,
+    error: Option<&'static str>,
+}
+
+impl NetworkBuilder {
+    pub fn new(len:u8) -> NetworkBuilder {
+        NetworkBuilder {len, stages: vec![Vec::new()], error: None}
+    }
+
+    // End the current stage, if it is not empty.
+    pub fn add_stage(mut self) -> NetworkBuilder {
+        if !self.stages.last().unwrap().is_empty() {
+            self.stages.push(Vec::new());
+        }
+        return self
+    }
+
+    // Add a lane-swap operation: smaller key to "lo", larger key to "hi".
+    // Errors are deferred until build() is called.
+    pub fn compare(mut self, lo:usize, hi:usize) -> NetworkBuilder {
+        let op = match LaneSwap::new(lo, hi) {
+            Ok(op) => op,
+            Err(e) => {self.error = self.error.or(Some(e)); return self},
+        };
+        let reuse = self.stages.last().unwrap().iter()
+            .any(|op| op.0 == lo || op.0 == hi || op.1 == lo || op.1 == hi);
+        if reuse {self = self.add_stage();}
+        self.stages.last_mut().unwrap().push(op);
+        return self
+    }
+
+    // Check that all lanes are in range and that the result is a
+    // permutation network, then generate the Network.
+    pub fn build(mut self) -> Result<Network, &'static str> {
+        if let Some(e) = self.error {return Err(e)}
+        if self.stages.last().unwrap().is_empty() {self.stages.pop();}
+        if self.stages.iter().flatten().any(|op| cmp::max(op.0, op.1) >= self.len as usize) {
+            return Err("Lane index out of range.")
+        }
+        let net = Network::new(self.len, self.stages);
+        if !is_permutation_network(&net) {
+            return Err("Lane used more than once in a stage.")
+        }
+        return Ok(net)
+    }
+}
+
+// Statically check that every stage of a network conserves its lanes,
+// i.e., that each lane-swap writes two distinct lanes in range, and that
+// no lane is written twice (and so another dropped) within a stage.
+// Operations in Mode::Shift discard a lane by design, so always fail.
+pub fn is_permutation_network(net:&Network) -> bool {
+    net.stages.iter().all(|stage| {
+        let mut used = vec![false; net.len as usize];
+        stage.iter().all(|op| {
+            let ok = op.2 != Mode::Shift && op.0 != op.1
+                && op.0 < used.len() && op.1 < used.len()
+                && !used[op.0] && !used[op.1];
+            if ok {used[op.0] = true; used[op.1] = true;}
+            ok
+        })
+    })
+}
+
+// Maintain the K smallest values from a stream, using a fixed network.
+// Each new value is appended to a sorted buffer of size K, then merged
+// into place by a chain of lane-swap operations; the largest is dropped.
+// The merge network has K+1 lanes, so K must be less than u8::MAX.
+pub struct StreamingTopK {
+    k: usize,
+    count: usize,
+    buffer: LaneArray,
+    merge: Network,
+}
+
+impl StreamingTopK {
+    pub fn new(k:usize) -> Result<StreamingTopK, String> {
+        if k >= u8::MAX as usize {
+            return Err(format!("K too large, must be less than {}.", u8::MAX));
+        }
+        let stages = (0..k).map(|n| vec![sw(n, k)]).collect();
+        Ok(StreamingTopK {
+            k,
+            count: 0,
+            buffer: LaneArray::from_keys(&vec![u64::MAX; k]),
+            merge: Network::new(k as u8 + 1, stages),
+        })
+    }
+
+    // Merge a new value into the buffer.
+    pub fn push(&mut self, value:u64) {
+        let mut x = self.buffer.clone();
+        x.lanes.push(Lane {key: value, payload: self.count as u64});
+        let mut y = self.merge.apply(&x);
+        y.lanes.truncate(self.k);
+        self.buffer = y;
+        self.count += 1;
+    }
+
+    // Return the sorted list of the K smallest values.
+    pub fn finish(self) -> Vec<u64> {
+        let mut keys = self.buffer.keys();
+        keys.truncate(cmp::min(self.count, self.k));
+        return keys
+    }
+}
+
+// Find the earliest stage after which two lanes with equal keys are
+// no longer in their original order, if any.
+pub fn first_unstable_stage(net:&Network, input:&LaneArray) -> Option<usize> {
+    net.apply_traced(input).iter().position(|p| !p.is_stable())
+}
+
+// For each output lane, find the index of the input lane that was routed
+// to it, by overwriting the metadata of each lane with its origin index.
+pub fn routing(net:&Network, input:&LaneArray) -> Vec<usize> {
+    let mut x = input.clone();
+    for (n, lane) in x.lanes.iter_mut().enumerate() {
+        lane.payload = n as u64;
+    }
+    return net.apply(&x).lanes.iter().map(|lane| lane.payload as usize).collect()
+}
+
+// Search random key vectors for an input that the network fails to sort.
+// Returns the failing keys and the seed for that trial, so that a failure
+// can be reproduced from the seed alone with a single trial.
+pub fn find_counterexample(net:&Network, seed:u64, trials:usize) -> Option<(u64, Vec<u64>)> {
+    let mut rng = Rng::new(seed);
+    for _ in 0..trials {
+        let trial_seed = rng.0;
+        let keys: Vec<u64> = (0..net.len).map(|_| rng.next() % 16).collect();
+        if !net.apply(&LaneArray::from_keys(&keys)).is_sorted_key() {
+            return Some((trial_seed, keys))
+        }
+    }
+    return None
+}
+
+// Replay a specific input, listing the state of the network after every
+// stage, e.g., to reproduce a failure found by find_counterexample().
+pub fn reproduce(net:&Network, keys:&[u64]) -> String {
+    let x = LaneArray::from_keys(keys);
+    let mut dump = format!("input\t{}\n", x);
+    for (k, p) in net.apply_traced(&x).iter().enumerate() {
+        dump += &format!("stage {}\t{}\n", k, p);
+    }
+    let last = net.apply(&x);
+    dump += if last.is_sorted_key() {"sorted\n"} else {"NOT SORTED\n"};
+    return dump
+}
+
+// For each output lane, find the stage index after which its value is
+// final for every zero-one input on "n" lanes, i.e., the earliest stage
+// where that output could be tapped.  (Lanes that are never modified
+// also report zero.)
+pub fn earliest_settled(net:&Network, n:u8) -> Vec<usize> {
+    let mut settled = vec![0usize; n as usize];
+    for mask in 0..1u64 << n {
+        let trace = net.apply_traced(&LaneArray::new(n, &LaneArrayType::Hidden(mask)));
+        let last = match trace.last() {Some(p) => p.keys(), None => break};
+        for (k, p) in trace.iter().enumerate() {
+            for (lane, key) in p.keys().iter().enumerate() {
+                if *key != last[lane] {settled[lane] = cmp::max(settled[lane], k + 1);}
+            }
+        }
+    }
+    return settled
+}
+
+// Find the maximum number of lane-swap operations that any single value
+// passes through from input to output.  This may be less than depth()
+// if some stages could be merged without changing the result.
+pub fn critical_path(net:&Network) -> usize {
+    let mut path = vec![0usize; net.len as usize];
+    for (_, LaneSwap(n1,n2,_)) in net.comparators() {
+        let next = cmp::max(path[*n1], path[*n2]) + 1;
+        path[*n1] = next;
+        path[*n2] = next;
+    }
+    return path.into_iter().max().unwrap_or(0)
+}
+
+// Fixed delay of one lane-swap operation, independent of data width,
+// i.e., the output multiplexer and routing, in picoseconds.
+pub const COMPARATOR_BASE_PS:f64 = 200.0;
+
+// First-order estimate of the combinational delay through a network, in
+// picoseconds.  Each lane-swap operation on the critical_path() is modeled
+// as a fixed delay plus a carry chain that grows linearly with data width,
+// so a deep network of narrow comparators may be faster than a shallow
+// network of wide ones.  Useful for comparing networks of equal depth.
+pub fn timing_estimate(net:&Network, data_width:usize, per_bit_delay_ps:f64) -> f64 {
+    let per_op = COMPARATOR_BASE_PS + data_width as f64 * per_bit_delay_ps;
+    return critical_path(net) as f64 * per_op
+}
+
+// List the pairs of lanes to compare on each cycle of a time-multiplexed
+// implementation that executes one stage per clock.  The first lane in
+// each pair receives the smaller key.
+pub fn schedule(net:&Network) -> Vec<Vec<(usize,usize)>> {
+    net.stages.iter()
+        .map(|stage| stage.iter().map(|op| (op.0, op.1)).collect())
+        .collect()
+}
+
+// Number of lane-swap operations in each stage, i.e., the number of
+// comparators needed on each cycle of the schedule().
+pub fn stage_widths(net:&Network) -> Vec<usize> {
+    net.stages.iter().map(|stage| stage.len()).collect()
+}
+
+// Number of physical comparators needed to execute any stage in a single
+// cycle, i.e., the width of the widest stage.
+pub fn max_parallelism(net:&Network) -> usize {
+    stage_widths(net).into_iter().max().unwrap_or(0)
+}
+
+// Generate a Verilog ROM initializer for the schedule() of a network.
+// Each word controls one cycle, and contains a field for each lane:
+// the index of its partner lane in the low bits (or its own index if
+// idle), and a flag above it that is set if it receives the smaller key.
+// If the network has any operations other than Mode::Swap, each field
+// also has a two-bit mode in the MSBs: 0 = swap, 1 = shift, 2 = exchange.
+// A network with no lanes or no stages has nothing to schedule, so its
+// ROM is a comment with no memory declaration.
+pub fn to_verilog_rom(net:&Network, name:&str) -> String {
+    let len = net.len as usize;
+    let idx_bits = cmp::max(1, (usize::BITS - len.saturating_sub(1).leading_zeros()) as usize);
+    let modes = net.comparators().any(|(_, op)| op.2 != Mode::Swap);
+    let field = idx_bits + if modes {3} else {1};
+    let mut rom = String::new();
+    rom += &format!("// Comparator schedule for {}: {} lanes, {} cycles.\n",
+                    name, len, net.depth());
+    if len == 0 || net.depth() == 0 {
+        rom += "// Empty schedule; no ROM required.\n";
+        return rom
+    }
+    rom += &format!("// Lane N uses bits [N*{} +: {}] = {{{}min_flag, partner[{}:0]}}.\n",
+                    field, field, if modes {"mode[1:0], "} else {""}, idx_bits - 1);
+    rom += &format!("reg [{}:0] {}_rom [0:{}];\n", len * field - 1, name, net.depth() - 1);
+    rom += "initial begin\n";
+    for (cycle, pairs) in schedule(net).iter().enumerate() {
+        let mut lanes: Vec<usize> = (0..len).collect();
+        for ((lo, hi), op) in pairs.iter().zip(net.stages[cycle].iter()) {
+            let mode = match op.2 {Mode::Swap => 0, Mode::Shift => 1, Mode::Exchange => 2};
+            lanes[*lo] = (mode << (idx_bits + 1)) | (1 << idx_bits) | hi;
+            lanes[*hi] = (mode << (idx_bits + 1)) | lo;
+        }
+        let fields: Vec<String> = lanes.iter().rev()
+            .map(|x| format!("{}'d{}", field, x)).collect();
+        rom += &format!("    {}_rom[{}] = {{{}}};\n", name, cycle, fields.join(", "));
+    }
+    rom += "end\n";
+    return rom
+}
+
+// Smallest data width that can hold PENALTY, as needed by shift operations.
+pub const SHIFT_WIDTH:usize = (u64::BITS - PENALTY.leading_zeros()) as usize;
+
+// Generate a combinational Verilog module for a network.  Inputs and
+// outputs are packed vectors, with lane N at bits [N*W +: W].  Operations
+// in Mode::Shift treat keys below PENALTY as valid, as in LaneArray::shift(),
+// so they require a data width that can hold PENALTY.
+pub fn to_verilog(net:&Network, name:&str, data_width:usize) -> String {
+    let len = net.len as usize;
+    let shifts = net.comparators().any(|(_, op)| op.2 == Mode::Shift);
+    assert!(!shifts || data_width >= SHIFT_WIDTH, "Data width too small for shift.");
+    let mut v = String::new();
+    v += &format!("// Sorting network {}: {} lanes, {} stages.\n", name, len, net.depth());
+    v += &format!("module {} (\n", name);
+    v += &format!("    input  wire [{}:0] in_data,\n", len * data_width - 1);
+    v += &format!("    output wire [{}:0] out_data\n", len * data_width - 1);
+    v += ");\n";
+    for lane in 0..len {
+        v += &format!("    wire [{}:0] s0_{} = in_data[{}*{} +: {}];\n",
+                      data_width - 1, lane, lane, data_width, data_width);
+    }
+    for (k, stage) in net.stages.iter().enumerate() {
+        let mut idle = vec![true; len];
+        for lane in 0..len {
+            v += &format!("    wire [{}:0] s{}_{};\n", data_width - 1, k + 1, lane);
+        }
+        for LaneSwap(n1,n2,mode) in stage.iter() {
+            match mode {
+                Mode::Swap => {
+                    let cmp = format!("(s{}_{} <= s{}_{})", k, n1, k, n2);
+                    v += &format!("    assign s{}_{} = {} ? s{}_{} : s{}_{};\n", k + 1, n1, cmp, k, n1, k, n2);
+                    v += &format!("    assign s{}_{} = {} ? s{}_{} : s{}_{};\n", k + 1, n2, cmp, k, n2, k, n1);
+                },
+                Mode::Shift => {
+                    let valid = format!("(s{}_{} < {})", k, n1, PENALTY);
+                    v += &format!("    assign s{}_{} = {} ? s{}_{} : s{}_{};\n", k + 1, n1, valid, k, n1, k, n2);
+                    v += &format!("    assign s{}_{} = {} ? s{}_{} : {};\n", k + 1, n2, valid, k, n2, PENALTY);
+                },
+                Mode::Exchange => {
+                    v += &format!("    assign s{}_{} = s{}_{};\n", k + 1, n1, k, n2);
+                    v += &format!("    assign s{}_{} = s{}_{};\n", k + 1, n2, k, n1);
+                },
+            }
+            idle[*n1] = false;
+            idle[*n2] = false;
+        }
+        for lane in (0..len).filter(|n| idle[*n]) {
+            v += &format!("    assign s{}_{} = s{}_{};\n", k + 1, lane, k, lane);
+        }
+    }
+    for lane in 0..len {
+        v += &format!("    assign out_data[{}*{} +: {}] = s{}_{};\n",
+                      lane, data_width, data_width, net.depth(), lane);
+    }
+    v += "endmodule\n";
+    return v
+}
+
+// Generate a self-checking Verilog testbench for the module created by
+// to_verilog().  It applies every zero-one input, which is sufficient to
+// verify any sorting network, but is practical only for small networks.
+// Networks with shift operations are instead given the inputs from
+// LaneArrayType::Simple, i.e., each disabled lane has key PENALTY.
+// Networks with exchange operations need not sort, so they are instead
+// given one input with each lane's index as its key, and each output lane
+// is checked against the input lane that the network routes to it.
+pub fn to_verilog_testbench(net:&Network, name:&str, data_width:usize) -> String {
+    let len = net.len as usize;
+    let shifts = net.comparators().any(|(_, op)| op.2 == Mode::Shift);
+    let exchanges = net.comparators().any(|(_, op)| op.2 == Mode::Exchange);
+    assert!(!exchanges || (usize::BITS - len.saturating_sub(1).leading_zeros()) as usize <= data_width,
+            "Data width too small for lane index.");
+    let mut v = String::new();
+    v += &format!("// Self-checking testbench for {}.\n", name);
+    v += &format!("module {}_tb;\n", name);
+    v += &format!("    reg  [{}:0] in_data;\n", len * data_width - 1);
+    v += &format!("    wire [{}:0] out_data;\n", len * data_width - 1);
+    v += "    integer mask, lane, errors;\n";
+    v += &format!("    {} dut (.in_data(in_data), .out_data(out_data));\n", name);
+    v += "    initial begin\n";
+    v += "        errors = 0;\n";
+    if exchanges {
+        let keys: Vec<u64> = (0..len as u64).collect();
+        let expected = net.apply(&LaneArray::from_keys(&keys)).keys();
+        v += &format!("        for (lane = 0; lane < {}; lane = lane + 1)\n", len);
+        v += &format!("            in_data[lane*{} +: {}] = lane;\n", data_width, data_width);
+        v += "        #1;\n";
+        for (lane, key) in expected.iter().enumerate() {
+            v += &format!("        if (out_data[{} +: {}] != {}) begin\n", lane * data_width, data_width, key);
+            v += &format!("            $display(\"FAIL: lane {}\");\n", lane);
+            v += "            errors = errors + 1;\n";
+            v += "        end\n";
+        }
+    } else {
+        v += &format!("        for (mask = 0; mask < {}; mask = mask + 1) begin\n", 1u64 << len);
+        v += &format!("            for (lane = 0; lane < {}; lane = lane + 1)\n", len);
+        if shifts {
+            v += &format!("                in_data[lane*{} +: {}] = ((mask >> lane) & 1) ? {} : lane;\n",
+                          data_width, data_width, PENALTY);
+        } else {
+            v += &format!("                in_data[lane*{} +: {}] = (mask >> lane) & 1;\n", data_width, data_width);
+        }
+        v += "            #1;\n";
+        v += &format!("            for (lane = 1; lane < {}; lane = lane + 1)\n", len);
+        v += &format!("                if (out_data[(lane-1)*{} +: {}] > out_data[lane*{} +: {}]) begin\n",
+                      data_width, data_width, data_width, data_width);
+        v += "                    $display(\"FAIL: mask %0d, lane %0d\", mask, lane);\n";
+        v += "                    errors = errors + 1;\n";
+        v += "                end\n";
+        v += "        end\n";
+    }
+    v += "        if (errors == 0) $display(\"PASS\");\n";
+    v += "        else $display(\"FAIL: %0d errors\", errors);\n";
+    v += "        $finish;\n";
+    v += "    end\n";
+    v += "endmodule\n";
+    return v
+}
+
+// Divide a stage into columns for drawing, such that lane-swap operations
+// in the same column do not overlap.  Returns operator indices.
+pub fn stage_columns(stage:&[LaneSwap]) -> Vec<Vec<usize>> {
+    let mut cols: Vec<Vec<usize>> = Vec::new();
+    let span = |op:&LaneSwap| (cmp::min(op.0, op.1), cmp::max(op.0, op.1));
+    for (n, op) in stage.iter().enumerate() {
+        let (lo, hi) = span(op);
+        let free = cols.iter().position(|col| col.iter().all(|m| {
+            let (a, b) = span(&stage[*m]);
+            hi < a || b < lo
+        }));
+        match free {
+            Some(c) => cols[c].push(n),
+            None => cols.push(vec![n]),
+        }
+    }
+    return cols
+}
+
+// Draw a network as ASCII art, with one row per lane.  Each lane-swap is
+// drawn as a vertical line, with "o" at the lane that receives the smaller
+// key and "*" at the lane that receives the larger key.  Shift operations
+// mark the lane that may be discarded with "#" instead of "*", and fixed
+// exchanges are marked with "x" at both ends.  Lanes that pass through a
+// stage untouched are drawn as "." instead of "-".
+pub fn render_ascii(net:&Network) -> String {
+    render_ascii_with(net, |_, _| None)
+}
+
+// As render_ascii(), but highlight selected lane-swap operations using
+// ANSI terminal colors.  The color function receives the stage index and
+// operator, and returns a color name (red, green, yellow, or blue).
+pub fn render_ascii_with(net:&Network, color:impl Fn(usize, &LaneSwap)->Option<&'static str>) -> String {
+    let len = net.len as usize;
+    let mut rows: Vec<String> = (0..len).map(|n| format!("{:>2} ", n)).collect();
+    for (k, stage) in net.stages.iter().enumerate() {
+        let idle = net.idle_lanes(k);
+        for col in stage_columns(stage).iter() {
+            let mut cells: Vec<char> = (0..len)
+                .map(|n| if idle.contains(&n) {'.'} else {'-'}).collect();
+            let mut paint: Vec<Option<&str>> = vec![None; len];
+            for op in col.iter().map(|m| &stage[*m]) {
+                for cell in cells.iter_mut()
+                    .take(cmp::max(op.0, op.1)).skip(cmp::min(op.0, op.1) + 1) {
+                    *cell = '|';
+                }
+                let (lo, hi) = match op.2 {
+                    Mode::Swap => ('o', '*'),
+                    Mode::Shift => ('o', '#'),
+                    Mode::Exchange => ('x', 'x'),
+                };
+                cells[op.min_out()] = lo;
+                cells[op.max_out()] = hi;
+                for p in paint.iter_mut()
+                    .take(cmp::max(op.0, op.1) + 1).skip(cmp::min(op.0, op.1)) {
+                    *p = color(k, op);
+                }
+            }
+            for (row, (cell, p)) in rows.iter_mut().zip(cells.iter().zip(paint.iter())) {
+                let wire = if *cell == '.' {'.'} else {'-'};
+                row.push(wire);
+                match p.map(ansi_code) {
+                    Some(code) => *row += &format!("\x1b[{}m{}\x1b[0m", code, cell),
+                    None => row.push(*cell),
+                }
+            }
+        }
+        for (n, row) in rows.iter_mut().enumerate() {
+            row.push(if idle.contains(&n) {'.'} else {'-'});
+            row.push(' ');
+        }
+    }
+    return rows.iter().map(|row| row.trim_end().to_string() + "\n").collect()
+}
+
+// ANSI terminal color code for each color name used by render_ascii_with().
+pub fn ansi_code(name:&str) -> &'static str {
+    match name {
+        "red"    => "31",
+        "green"  => "32",
+        "yellow" => "33",
+        "blue"   => "34",
+        _        => "0",
+    }
+}
+
+// Draw a network as an SVG image, using the same column layout as
+// render_ascii().  Filled circles mark the lane that receives the smaller
+// key.  Shift operations mark the lane that may be discarded with a square,
+// and fixed exchanges are drawn with open circles at both ends.  Lanes that
+// pass through a stage untouched are drawn as dashed grey lines.
+pub fn to_svg(net:&Network) -> String {
+    to_svg_with(net, |_, _| "black".to_string())
+}
+
+// As to_svg(), but draw each lane-swap operation using the color returned
+// by the color function, which receives the stage index and operator.
+pub fn to_svg_with(net:&Network, color:impl Fn(usize, &LaneSwap)->String) -> String {
+    const STEP:usize = 20;
+    let len = net.len as usize;
+    let cols: Vec<Vec<Vec<usize>>> = net.stages.iter()
+        .map(|stage| stage_columns(stage)).collect();
+    let total: usize = cols.iter().map(|c| c.len() + 1).sum();
+    let (w, h) = ((total + 1) * STEP, (len + 1) * STEP);
+    let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">\n", w, h);
+    let mut x = STEP / 2;
+    for (k, stage) in net.stages.iter().enumerate() {
+        // Wire segments for this stage.
+        let x1 = x + (cols[k].len() + 1) * STEP;
+        let idle = net.idle_lanes(k);
+        for n in 0..len {
+            let y = (n + 1) * STEP;
+            let style = if idle.contains(&n) {
+                "stroke=\"grey\" stroke-dasharray=\"2,2\""
+            } else {
+                "stroke=\"black\""
+            };
+            svg += &format!("  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" {}/>\n", x, y, x1, y, style);
+        }
+        // Lane-swap operations for this stage.
+        for (c, col) in cols[k].iter().enumerate() {
+            let cx = x + (c + 1) * STEP;
+            for op in col.iter().map(|m| &stage[*m]) {
+                let c = color(k, op);
+                let (y0, y1) = ((op.min_out() + 1) * STEP, (op.max_out() + 1) * STEP);
+                svg += &format!("  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"{}\"/>\n", cx, y0, cx, y1, c);
+                if op.2 == Mode::Exchange {
+                    svg += &format!("  <circle cx=\"{}\" cy=\"{}\" r=\"3\" fill=\"white\" stroke=\"{}\"/>\n", cx, y0, c);
+                } else {
+                    svg += &format!("  <circle cx=\"{}\" cy=\"{}\" r=\"3\" fill=\"{}\"/>\n", cx, y0, c);
+                }
+                if op.2 == Mode::Shift {
+                    svg += &format!("  <rect x=\"{}\" y=\"{}\" width=\"6\" height=\"6\" fill=\"white\" stroke=\"{}\"/>\n", cx - 3, y1 - 3, c);
+                } else {
+                    svg += &format!("  <circle cx=\"{}\" cy=\"{}\" r=\"3\" fill=\"white\" stroke=\"{}\"/>\n", cx, y1, c);
+                }
+            }
+        }
+        x = x1;
+    }
+    svg += "</svg>\n";
+    return svg
+}
+
+// For each lane-swap operation, in the order listed by comparators(),
+// find the fraction of zero-one inputs on "n" lanes for which it exchanges
+// its inputs.  Frequent swaps indicate high switching activity and power.
+pub fn swap_frequency(net:&Network, n:u8) -> Vec<f64> {
+    let max_mask = 1u64 << n;
+    let mut counts = vec![0u64; net.size()];
+    for mask in 0..max_mask {
+        let mut p = LaneArray::new(n, &LaneArrayType::Hidden(mask));
+        let mut idx = 0usize;
+        for stage in net.stages.iter() {
+            for op in stage.iter() {
+                if op.swaps::<Ascending, u64>(&p.lanes[op.min_out()], &p.lanes[op.max_out()]) {counts[idx] += 1;}
+                idx += 1;
+            }
+            p = p.swap_by::<Ascending>(stage, 1);
+        }
+    }
+    return counts.iter().map(|c| *c as f64 / max_mask as f64).collect()
+}
+
+// Draw a network as an SVG heatmap, where each lane-swap operation is
+// colored from blue (rarely swaps) to red (swaps half the time or more).
+pub fn to_svg_heatmap(net:&Network) -> String {
+    let freq = swap_frequency(net, net.len);
+    let offsets: Vec<usize> = net.stages.iter()
+        .scan(0, |sum, stage| {let k = *sum; *sum += stage.len(); Some(k)}).collect();
+    to_svg_with(net, |k, op| {
+        let idx = offsets[k] + net.stages[k].iter().position(|x| x == op).unwrap();
+        let heat = (2.0 * freq[idx]).min(1.0);
+        format!("rgb({},0,{})", (255.0 * heat) as u8, (255.0 * (1.0 - heat)) as u8)
+    })
+}
+
+// Move each lane-swap operation to the earliest possible stage, i.e.,
+// immediately after the last operation on either of its lanes.
+pub fn compact(net:&Network) -> Network {
+    let mut ready = vec![0usize; net.len as usize];
+    let mut stages: Vec<Vec<LaneSwap>> = Vec::new();
+    for (_, op) in net.comparators() {
+        let k = cmp::max(ready[op.0], ready[op.1]);
+        if k == stages.len() {stages.push(Vec::new());}
+        stages[k].push(*op);
+        ready[op.0] = k + 1;
+        ready[op.1] = k + 1;
+    }
+    Network::new(net.len, stages)
+}
+
+// Normalize a network by compacting it, then sorting the operations in
+// each stage, so that equivalent schedules produce identical networks.
+pub fn canonicalize(net:&Network) -> Network {
+    let mut result = compact(net);
+    for stage in result.stages.iter_mut() {
+        stage.sort_by_key(|op| (cmp::min(op.0, op.1), cmp::max(op.0, op.1)));
+    }
+    return result
+}
+
+// Are two networks the same, up to scheduling of their stages?
+pub fn structurally_equal(a:&Network, b:&Network) -> bool {
+    canonicalize(a) == canonicalize(b)
+}
+
+// Differences between two networks with the same number of lanes.
+// Each entry lists the stage index and the lane-swap operation; moved
+// operations list the original and new stage index.
+pub struct NetworkDiff {
+    pub added: Vec<(usize, LaneSwap)>,
+    pub removed: Vec<(usize, LaneSwap)>,
+    pub moved: Vec<(usize, usize, LaneSwap)>,
+}
+
+// Find the lane-swap operations that were added, removed, or moved to a
+// different stage.  Operations found in the same stage of both networks
+// are matched first; the remainder are matched in execution order.
+pub fn diff(a:&Network, b:&Network) -> NetworkDiff {
+    assert_eq!(a.len, b.len);
+    let mut removed: Vec<(usize, LaneSwap)> = a.comparators()
+        .filter(|(k, op)| !b.stages.get(*k).is_some_and(|stage| stage.contains(op)))
+        .map(|(k, op)| (k, *op)).collect();
+    let mut added: Vec<(usize, LaneSwap)> = b.comparators()
+        .filter(|(k, op)| !a.stages.get(*k).is_some_and(|stage| stage.contains(op)))
+        .map(|(k, op)| (k, *op)).collect();
+    let mut moved = Vec::new();
+    let mut n = 0usize;
+    while n < removed.len() {
+        let (from, op) = removed[n];
+        match added.iter().position(|(_, x)| *x == op) {
+            Some(m) => {
+                moved.push((from, added.remove(m).0, op));
+                removed.remove(n);
+            },
+            None => n += 1,
+        }
+    }
+    NetworkDiff {added, removed, moved}
+}
+
+impl NetworkDiff {
+    // Build a network containing every operation from "b", plus each
+    // removed operation in its original stage, so that both can be drawn
+    // in the same diagram.
+    pub fn overlay(&self, b:&Network) -> Network {
+        let mut result = b.clone();
+        for (k, op) in self.removed.iter() {
+            while result.stages.len() <= *k {result.stages.push(Vec::new());}
+            result.stages[*k].push(*op);
+        }
+        return result
+    }
+
+    // Color for an operation in the overlay() diagram: green if added,
+    // red if removed, yellow if moved, or None if unchanged.
+    pub fn color(&self, k:usize, op:&LaneSwap) -> Option<&'static str> {
+        if self.added.contains(&(k, *op)) {
+            Some("green")
+        } else if self.removed.contains(&(k, *op)) {
+            Some("red")
+        } else if self.moved.iter().any(|(_, to, x)| *to == k && x == op) {
+            Some("yellow")
+        } else {
+            None
+        }
+    }
+}
+
+// Flip the direction of every lane-swap operation, so that the network
+// sorts in descending order instead of ascending order.
+pub fn reversed(net:&Network) -> Network {
+    let stages = net.stages.iter()
+        .map(|stage| stage.iter().map(|op| LaneSwap(op.1, op.0, op.2)).collect())
+        .collect();
+    Network::new(net.len, stages)
+}
+
+// Write a network in a plain-text format, with one line per stage.
+// Each lane-swap is written as "lo:hi", or as "lo>hi" in Mode::Shift,
+// or as "lo~hi" in Mode::Exchange.
+pub fn to_text(net:&Network) -> String {
+    let mut text = format!("lanes {}\n", net.len);
+    for stage in net.stages.iter() {
+        let ops: Vec<String> = stage.iter().map(|op| match op.2 {
+            Mode::Swap  => format!("{}:{}", op.0, op.1),
+            Mode::Shift => format!("{}>{}", op.0, op.1),
+            Mode::Exchange => format!("{}~{}", op.0, op.1),
+        }).collect();
+        text += &ops.join(" ");
+        text += "\n";
+    }
+    return text
+}
+
+// Largest network that can be tested, since each test case is a mask
+// with one bit per lane, and 1u64 << n must not overflow.
+pub const MAX_LANES:usize = 63;
+
+// Read a network in the format written by to_text().  Blank lines and
+// anything following a "#" are ignored.  If the "lanes" line is missing,
+// the number of lanes is inferred from the largest index.
+pub fn parse_network(text:&str) -> Result<Network, String> {
+    let mut len: Option<usize> = None;
+    let mut stages: Vec<Vec<LaneSwap>> = Vec::new();
+    for (n, line) in text.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        let err = |msg:&str| format!("Line {}: {}", n + 1, msg);
+        if line.is_empty() {continue}
+        if let Some(arg) = line.strip_prefix("lanes") {
+            len = Some(arg.trim().parse().map_err(|_| err("Invalid lane count."))?);
+            continue
+        }
+        let mut stage = Vec::new();
+        for word in line.split_whitespace() {
+            let (mode, sep) = if word.contains('>') {(Mode::Shift, '>')}
+                else if word.contains('~') {(Mode::Exchange, '~')}
+                else {(Mode::Swap, ':')};
+            let mut idx = word.splitn(2, sep).map(|x| x.parse::<usize>());
+            let (a, b) = match (idx.next(), idx.next()) {
+                (Some(Ok(a)), Some(Ok(b))) => (a, b),
+                _ => return Err(err(&format!("Invalid lane-swap: {}", word))),
+            };
+            let op = LaneSwap::new(a, b).map_err(err)?;
+            if stage.iter().any(|x:&LaneSwap| x.0 == a || x.0 == b || x.1 == a || x.1 == b) {
+                return Err(err("Lane used more than once in a stage."))
+            }
+            stage.push(LaneSwap(op.0, op.1, mode));
+        }
+        stages.push(stage);
+    }
+    let max = stages.iter().flatten().map(|op| cmp::max(op.0, op.1) + 1).max().unwrap_or(0);
+    let len = len.unwrap_or(max);
+    if max > len {return Err("Lane index out of range.".to_string())}
+    if len > MAX_LANES {return Err(format!("Too many lanes, at most {}.", MAX_LANES))}
+    return Ok(Network::new(len as u8, stages))
+}
+
+// Periodic balanced sorting network (Dowd, Perl, Rudolph, and Saks).
+// Each round is a balanced merger of log2(n) stages; stage "j" divides the
+// lanes into blocks of n/2^j lanes and compares mirror-image pairs within
+// each block.  Every round is identical, which suits a pipeline that loops
+// data through the same hardware.  For n = 2^k lanes, the network is
+// guaranteed to sort after k rounds, for a total depth of k^2 stages.
+pub fn periodic_network(n:usize, rounds:usize) -> Network {
+    assert!(n.is_power_of_two() && n <= 64);
+    let mut round: Vec<Vec<LaneSwap>> = Vec::new();
+    let mut block = n;
+    while block > 1 {
+        round.push((0..n).filter(|i| i % block < block / 2)
+            .map(|i| sw(i, i + block - 1 - 2 * (i % block))).collect());
+        block /= 2;
+    }
+    let stages = (0..rounds).flat_map(|_| round.clone()).collect();
+    return Network::new(n as u8, stages)
+}
+
+// Build a network of fixed exchanges that rearranges any input, so that
+// output lane "i" receives input lane perm[i].  The network performs an
+// odd-even transposition sort on the target positions, so it has at most
+// perm.len() stages.
+pub fn permutation_network(perm:&[usize]) -> Network {
+    let n = perm.len();
+    let mut rank = vec![n; n];
+    for (pos, v) in perm.iter().enumerate() {
+        assert!(*v < n && rank[*v] == n, "Not a permutation: {:?}", perm);
+        rank[*v] = pos;
+    }
+    let mut state: Vec<usize> = (0..n).collect();
+    let mut stages = Vec::new();
+    for round in 0..n {
+        let mut stage = Vec::new();
+        for i in (round % 2..n.saturating_sub(1)).step_by(2) {
+            if rank[state[i]] > rank[state[i+1]] {
+                stage.push(ex(i, i+1));
+                state.swap(i, i+1);
+            }
+        }
+        if !stage.is_empty() {stages.push(stage);}
+    }
+    return Network::new(n as u8, stages)
+}
+
+// Bitonic merger for n = 2^k lanes: each stage splits the lanes into
+// blocks and compares each lane in the first half of a block to the
+// corresponding lane in the second half.
+pub fn bitonic_merge_network(n:usize) -> Network {
+    assert!(n.is_power_of_two() && n <= 64);
+    let mut stages = Vec::new();
+    let mut block = n;
+    while block > 1 {
+        stages.push((0..n).filter(|i| i % block < block / 2)
+            .map(|i| sw(i, i + block / 2)).collect());
+        block /= 2;
+    }
+    return Network::new(n as u8, stages)
+}
+
+// Sort a bitonic sequence.  The result is incorrect for any other input.
+pub fn bitonic_merge(p0:&LaneArray) -> LaneArray {
+    debug_assert!(p0.is_bitonic(), "bitonic_merge input is not bitonic: {}", p0);
+    return bitonic_merge_network(p0.lanes.len()).apply(p0)
+}
+
+// Selection network that moves the "k" smallest keys to the first "k"
+// output lanes, in order.  Each pass bubbles the smallest remaining key
+// downward, and compaction lets successive passes overlap.
+pub fn select_network(n:usize, k:usize) -> Network {
+    let mut builder = NetworkBuilder::new(n as u8);
+    for j in 0..k {
+        for i in (j+1..n).rev() {
+            builder = builder.compare(i-1, i);
+        }
+    }
+    return compact(&builder.build().unwrap())
+}
+
+// Simple pseudorandom number generator (xorshift64*), so that sampled
+// tests are repeatable and need no external dependencies.
+struct Rng(u64);
+
+// Default seed for sampled tests.
+pub const SEED:u64 = 0x2545F4914F6CDD1D;
+
+impl Rng {
+    fn new(seed:u64) -> Rng {
+        Rng {0: cmp::max(seed, 1)}
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        return self.0.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+}
+
+// Command-line options that control the test harness.
+pub struct TestOptions {
+    pub progress: bool,         // Report masks processed to stderr
+    pub sample: Option<u64>,    // Test N random masks instead of all masks
+    pub fail_fast: bool,        // Stop at the first sorting error
+    pub dump_unstable: bool,    // Report where each unstable network fails
+    pub encodings: Vec<EncodingStrategy>,   // Key encodings to test
+}
+
+impl Default for TestOptions {
+    fn default() -> TestOptions {
+        TestOptions {
+            progress: false,
+            sample: None,
+            fail_fast: false,
+            dump_unstable: false,
+            encodings: vec![LaneArrayType::Simple, LaneArrayType::Hidden],
+        }
+    }
+}
+
+impl TestOptions {
+    // Parse options from the command-line arguments.
+    pub fn parse(args:&[String]) -> Result<TestOptions, String> {
+        let mut opts = TestOptions::default();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--progress" => opts.progress = true,
+                "--fail-fast" => opts.fail_fast = true,
+                "--dump-unstable" => opts.dump_unstable = true,
+                "--encoding" => {
+                    let names = iter.next().map(|s| s.as_str()).unwrap_or("");
+                    let list: Option<Vec<EncodingStrategy>> =
+                        names.split(',').map(LaneArrayType::parse).collect();
+                    if list.is_none() {return Err(format!("Unknown encoding: {}", names))}
+                    opts.encodings = list.unwrap();
+                },
+                "--sample" => {
+                    let count = iter.next().and_then(|n| n.parse().ok());
+                    if count.is_none() {return Err("Expected --sample N".to_string())}
+                    opts.sample = count;
+                },
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
+        return Ok(opts)
+    }
+}
+
+// Progress callback for long runs: masks processed out of the total.
+// Written to stderr so the summary report on stdout is unchanged.
+pub fn show_progress(lbl:&str, done:u64, total:u64) {
+    eprint!("\r{}\t {}/{}", lbl.trim_end(), done, total);
+    let _ = std::io::stderr().flush();
+}
+
+// Remove any lane-swap operation that exactly repeats the most recent
+// operation on both of its lanes, since the repeat is always a no-op.
+// Stages that become empty are removed entirely.  Returns the new network
+// and the number of lane-swap operations that were removed.
+pub fn dedup_comparators(net:&Network) -> (Network, usize) {
+    let mut last: Vec<Option<(usize, LaneSwap)>> = vec![None; net.len as usize];
+    let mut stages = Vec::new();
+    let mut count = 0usize;
+    for stage in net.stages.iter() {
+        let mut keep = Vec::new();
+        for op in stage.iter() {
+            let prev = last[op.0];
+            if op.2 != Mode::Exchange && prev.is_some() && prev == last[op.1] && prev.unwrap().1 == *op {
+                continue
+            }
+            last[op.0] = Some((count, *op));
+            last[op.1] = Some((count, *op));
+            keep.push(*op);
+            count += 1;
+        }
+        if !keep.is_empty() {stages.push(keep);}
+    }
+    (Network::new(net.len, stages), net.size() - count)
+}
+
+// Upper limit on the number of search nodes visited by search_optimal().
+pub const SEARCH_LIMIT:u64 = 10_000_000;
+
+// State of the search_optimal() function.  Each zero-one input vector is
+// represented as a bit-mask, with bit N indicating the value of lane N.
+// The state is the sorted set of all reachable output vectors.
+struct Search {
+    len: usize,
+    nodes: u64,
+    failed: HashMap<Vec<u64>, usize>,
+    ops: Vec<LaneSwap>,
+}
+
+impl Search {
+    // Apply a lane-swap operation to every vector in the set.
+    fn step(state:&[u64], op:&LaneSwap) -> Vec<u64> {
+        let (m0, m1) = (1u64 << op.0, 1u64 << op.1);
+        let mut next: Vec<u64> = state.iter()
+            .map(|v| if v & m0 > 0 && v & m1 == 0 {v ^ m0 ^ m1} else {*v})
+            .collect();
+        next.sort_unstable();
+        next.dedup();
+        return next
+    }
+
+    // A vector is sorted if its "0" bits are contiguous at the bottom.
+    fn is_sorted(&self, state:&[u64]) -> bool {
+        let all = (1u64 << self.len) - 1;
+        state.iter().all(|v| {let z = !v & all; z & (z + 1) == 0})
+    }
+
+    // Depth-first search for a sorting network using at most "budget"
+    // additional lane-swap operations.  Returns true if successful.
+    fn dfs(&mut self, state:&[u64], budget:usize) -> bool {
+        if self.is_sorted(state) {return true}
+        if budget == 0 || self.nodes >= SEARCH_LIMIT {return false}
+        if self.failed.get(state).is_some_and(|b| *b >= budget) {return false}
+        self.nodes += 1;
+        for a in 0..self.len {
+            for b in a+1..self.len {
+                // Adjacent operations on disjoint lanes commute, so only
+                // try such pairs in one canonical order.
+                let op = sw(a, b);
+                if let Some(prev) = self.ops.last() {
+                    let disjoint = prev.0 != a && prev.0 != b
+                                && prev.1 != a && prev.1 != b;
+                    if disjoint && (a, b) < (prev.0, prev.1) {continue}
+                }
+                // Skip operations that have no effect.
+                let next = Search::step(state, &op);
+                if next == state {continue}
+                self.ops.push(op);
+                if self.dfs(&next, budget - 1) {return true}
+                self.ops.pop();
+            }
+        }
+        self.failed.insert(state.to_vec(), budget);
+        return false
+    }
+}
+
+// Search for a sorting network with the fewest possible lane-swap
+// operations, up to the designated maximum, using iterative deepening
+// and the zero-one principle.  Candidates are validated as they are
+// built, by tracking the set of reachable zero-one output vectors.
+//
+// This is practical only for small networks: N <= 5 completes in well
+// under a second, while N = 6 may take several seconds.  The search is
+// capped at SEARCH_LIMIT nodes, returning None if the limit is reached.
+pub fn search_optimal(n:usize, max_comparators:usize) -> Option<Network> {
+    let mut search = Search {len:n, nodes:0, failed:HashMap::new(), ops:Vec::new()};
+    let start: Vec<u64> = (0..1u64 << n).collect();
+    for budget in 0..=max_comparators {
+        if search.dfs(&start, budget) {
+            // Pack the resulting operations into stages.
+            let mut builder = NetworkBuilder::new(n as u8);
+            for op in search.ops.iter() {
+                builder = builder.compare(op.0, op.1);
+            }
+            return builder.build().ok()
+        }
+    }
+    return None
+}
+
+// Enumerate every test case for "n" lanes: each keep/discard mask, once
+// in Simple indexing mode and once in Hidden indexing mode.  Cases are
+// generated lazily, so callers may filter, sample, or split them.
+pub fn test_cases(n:usize) -> impl Iterator<Item = (u64, LaneArrayType)> {
+    test_cases_with(n, &TestOptions::default())
+}
+
+// As test_cases(), but using the key encodings and sampling mode from the
+// command-line options.  In sampling mode, masks are chosen at random.
+pub fn test_cases_with(n:usize, opts:&TestOptions) -> impl Iterator<Item = (u64, LaneArrayType)> {
+    test_cases_for(sample_masks(n, opts.sample), &opts.encodings)
+}
+
+// Number of masks tested for "n" lanes: all 2^n masks, or the requested
+// sample size if that is smaller.
+pub fn sample_count(n:usize, sample:Option<u64>) -> u64 {
+    let max_mask = 1u64 << n;
+    sample.map_or(max_mask, |count| cmp::min(count, max_mask))
+}
+
+// Enumerate the keep/discard masks for "n" lanes: every mask in order, or
+// in sampling mode, sample_count() distinct masks chosen at random.  A
+// sample that covers every mask is the same as the exhaustive test.
+pub fn sample_masks(n:usize, sample:Option<u64>) -> Box<dyn Iterator<Item = u64>> {
+    let max_mask = 1u64 << n;
+    let count = sample_count(n, sample);
+    if count == max_mask {return Box::new(0..max_mask)}
+    let mut rng = Rng::new(SEED);
+    let mut seen = HashSet::new();
+    Box::new(std::iter::repeat_with(move || rng.next() % max_mask)
+        .filter(move |mask| seen.insert(*mask))
+        .take(count as usize))
+}
+
+// Expand a list of keep/discard masks into test cases, once for each of
+// the designated key encodings, e.g., to test only a subset of masks.
+pub fn test_cases_for(masks:impl Iterator<Item = u64>, encodings:&[EncodingStrategy])
+    -> impl Iterator<Item = (u64, LaneArrayType)>
+{
+    let encodings = encodings.to_vec();
+    masks.flat_map(move |mask| encodings.clone().into_iter().map(move |enc| (mask, enc(mask))))
+}
+
+// Outcome of a single test case.
+pub struct CaseResult {
+    pub output: LaneArray,  // Output of the sorting function
+    pub sorted: bool,       // Output is sorted by key, for valid lanes
+    pub stable: bool,       // Output is sorted by metadata
+}
+
+// Apply a sorting function to a single input and check the output.
+pub fn check_case_fn(sortfn:impl Fn(&LaneArray)->LaneArray, input:&LaneArray) -> CaseResult {
+    let output = sortfn(input);
+    let sorted = output.is_sorted_valid();
+    let stable = output.is_sorted_meta();
+    CaseResult {output, sorted, stable}
+}
+
+// As check_case_fn(), for a network.
+pub fn check_case(net:&Network, input:&LaneArray) -> CaseResult {
+    check_case_fn(|x| net.apply(x), input)
+}
+
+// Given a sorting function, test that it functions correctly
+// and then report whether it preserves order in case of ties.
+pub fn test_sort(len:u8, lbl:&str, sortfn:impl Fn(&LaneArray)->LaneArray, opts:&TestOptions) -> Stability {
+    // Test that sorting is correct for each possible enable mask,
+    // counting violations in each of the selected indexing modes.
+    // In sampling mode, test randomly-selected masks instead.
+    let start = Instant::now();
+    let count = sample_count(len as usize, opts.sample);
+    let per_mask = cmp::max(opts.encodings.len(), 1);
+    let step = cmp::max(count / 100, 1);
+    let mut err_key = 0u64;
+    let mut err_meta = 0u64;
+    let mut done = 0u64;
+    for (k, (mask, typ)) in test_cases_with(len as usize, opts).enumerate() {
+        if k.is_multiple_of(per_mask) {
+            if opts.progress && done.is_multiple_of(step) {
+                show_progress(lbl, done, count);
+            }
+            done += 1;
+        }
+        let x = LaneArray::new(len, &typ);
+        let result = check_case_fn(&sortfn, &x);
+        if !result.sorted {
+            println!("x = {}", x);
+            println!("y = {}", result.output);
+            err_key += 1;
+            if opts.fail_fast {
+                println!("{}\t Stopped at mask {:#x}.", lbl, mask);
+                break
+            }
+        }
+        // Once sorting has failed, stability is irrelevant.
+        if err_key == 0 && !result.stable {
+            err_meta += 1;
+        }
+    }
+    if opts.progress {
+        show_progress(lbl, done, count);
+        eprintln!();
+    }
+
+    // Summary report, including wall-clock time:
+    let note = if count < 1u64 << len {
+        format!(" (sampled {} of 2^{} masks, not exhaustive)", count, len)
+    } else {
+        String::new()
+    } + &format!(" [{} ms]", start.elapsed().as_millis());
+    let verdict = Stability::from_errors(err_key, err_meta);
+    match verdict {
+        Stability::Incorrect => println!("{}\t Sorting error.{}", lbl, note),
+        Stability::Unstable  => println!("{}\t Order not preserved.{}", lbl, note),
+        Stability::Stable    => println!("{}\t All tests passed.{}", lbl, note),
+    }
+    return verdict
+}
+
+// Function pointer for the hardcoded sorting functions.
+pub type SortFn = fn(&LaneArray)->LaneArray;
+
+// Three-way verdict for a sorting function.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stability {
+    Incorrect,  // Output is not sorted by key
+    Unstable,   // Output is sorted, but ties may change order
+    Stable,     // Output is sorted, and ties preserve order
+}
+
+impl Stability {
+    // Verdict based on the number of sorting and ordering violations.
+    pub fn from_errors(err_key:u64, err_meta:u64) -> Stability {
+        if err_key > 0 {
+            Stability::Incorrect
+        } else if err_meta > 0 {
+            Stability::Unstable
+        } else {
+            Stability::Stable
+        }
+    }
+}
+
+// Exhaustively test a sorting function in both Simple and Hidden
+// indexing modes, and classify the result.
+pub fn classify_fn(len:u8, sortfn:impl Fn(&LaneArray)->LaneArray) -> Stability {
+    let mut err_key = 0u64;
+    let mut err_meta = 0u64;
+    for (_, typ) in test_cases(len as usize) {
+        let result = check_case_fn(&sortfn, &LaneArray::new(len, &typ));
+        if !result.sorted {err_key += 1;}
+        if !result.stable {err_meta += 1;}
+    }
+    return Stability::from_errors(err_key, err_meta)
+}
+
+// As classify_fn(), for a network with "n" lanes.
+pub fn classify(net:&Network, n:u8) -> Stability {
+    classify_fn(n, |x| net.apply(x))
+}
+
+// Use the zero-one principle to test whether a network sorts correctly:
+// it is sufficient to test every input consisting only of 0s and 1s.
+// In sampling mode, test randomly-selected binary inputs instead.
+pub fn is_sorting_network(net:&Network, sample:Option<u64>) -> bool {
+    for mask in sample_masks(net.len as usize, sample) {
+        let x = LaneArray::new(net.len, &LaneArrayType::Hidden(mask));
+        if !net.apply(&x).is_sorted_key() {return false}
+    }
+    return true
+}
+
+// List the exact enable masks for which a network fails on "n" lanes, in
+// either Simple or Hidden indexing mode: first the masks that are not
+// sorted correctly, then the masks that are sorted but not stable.
+pub fn failing_masks(net:&Network, n:usize) -> (Vec<u64>, Vec<u64>) {
+    let mut incorrect: Vec<u64> = Vec::new();
+    let mut unstable: Vec<u64> = Vec::new();
+    for (mask, typ) in test_cases(n) {
+        let result = check_case(net, &LaneArray::new(n as u8, &typ));
+        let list = if !result.sorted {&mut incorrect}
+                   else if !result.stable {&mut unstable}
+                   else {continue};
+        if list.last() != Some(&mask) {list.push(mask);}
+    }
+    unstable.retain(|mask| incorrect.binary_search(mask).is_err());
+    return (incorrect, unstable)
+}
+
+// Maximum number of entries in PREFIX_CACHE before it is cleared.
+const PREFIX_CACHE_LIMIT:usize = 64;
+
+// Largest network cached by verify_after_edit().  Each entry may hold up
+// to 2^N states, so this bounds the size of each entry as well.
+const PREFIX_CACHE_MAX_LANES:usize = 20;
+
+thread_local! {
+    // Set of reachable zero-one states after a prefix of a network, as used
+    // by Search, keyed by the to_text() of the prefix.  Since the key is the
+    // prefix itself, an edit can never leave a stale entry.
+    static PREFIX_CACHE: RefCell<HashMap<String, Vec<u64>>> = RefCell::new(HashMap::new());
+}
+
+// As is_sorting_network(), but for interactive editing of a network where
+// only stages from "changed_stage" onward differ from a previous call.
+// The reachable zero-one states after every stage are cached, so only the
+// remaining stages are re-run.  Falls back to full verification if "n" is
+// not the network size, if the network is larger than PREFIX_CACHE_MAX_LANES,
+// or if the network contains Mode::Shift operations.
+pub fn verify_after_edit(net:&Network, changed_stage:usize, n:usize) -> bool {
+    let cacheable = n == net.len as usize && n <= PREFIX_CACHE_MAX_LANES
+        && net.comparators().all(|(_, op)| op.2 == Mode::Swap);
+    if !cacheable {return is_sorting_network(net, None)}
+    let k = cmp::min(changed_stage, net.depth());
+    let prefix = |k:usize| to_text(&Network::new(net.len, net.stages[..k].to_vec()));
+    PREFIX_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        let mut state = match cache.get(&prefix(k)) {
+            Some(state) => state.clone(),
+            None => {
+                let mut state: Vec<u64> = (0..1u64 << n).collect();
+                for stage in net.stages[..k].iter() {
+                    for op in stage.iter() {state = Search::step(&state, op);}
+                }
+                state
+            },
+        };
+        if cache.len() + net.depth() > PREFIX_CACHE_LIMIT {cache.clear();}
+        cache.insert(prefix(k), state.clone());
+        for j in k..net.depth() {
+            for op in net.stages[j].iter() {state = Search::step(&state, op);}
+            cache.insert(prefix(j + 1), state.clone());
+        }
+        let all = (1u64 << n) - 1;
+        state.iter().all(|v| {let z = !v & all; z & (z + 1) == 0})
+    })
+}
+
+// Weaker test for the packing application, where the disabled lanes are
+// always contiguous (e.g., a prefix or suffix of the lanes): do all such
+// enable masks produce sorted outputs?
+pub fn is_correct_for_contiguous_masks(net:&Network, n:u8) -> bool {
+    let len = n as u64;
+    let runs = (0..len).flat_map(move |start| (1..=len - start).map(move |k| ((1u64 << k) - 1) << start));
+    let encodings = TestOptions::default().encodings;
+    test_cases_for(std::iter::once(0).chain(runs), &encodings)
+        .all(|(_, typ)| check_case(net, &LaneArray::new(n, &typ)).sorted)
+}
+
+// Confirm that every built-in network passes the zero-one test.
+// Returns false if any network fails.
+pub fn test_zero_one(lbl:&str, opts:&TestOptions) -> bool {
+    let failed: Vec<&str> = builtin_networks().iter()
+        .filter(|(_, net)| !is_sorting_network(net, opts.sample))
+        .map(|(name, _)| *name).collect();
+    if failed.is_empty() {
+        println!("{}\t All tests passed.", lbl);
+    } else {
+        println!("{}\t Sorting error: {}", lbl, failed.join(", "));
+    }
+    return failed.is_empty()
+}
+
+// Find the first input that the network fails to keep in order, and
+// report the stage that introduced the stability violation.
+pub fn dump_unstable(lbl:&str, net:&Network) {
+    for (_, typ) in test_cases(net.len as usize) {
+        let x = LaneArray::new(net.len, &typ);
+        if check_case(net, &x).stable {continue}
+        if let Some(stage) = first_unstable_stage(net, &x) {
+            println!("{}\t Unstable at stage {}: x = {}", lbl, stage, x);
+            return
+        }
+    }
+}
+
+// Example of a custom ordering: ascending by key, then by metadata.
+pub struct KeyThenMeta;
+
+impl Comparator for KeyThenMeta {
+    fn order(a:&Lane, b:&Lane) -> bool {(a.key, a.payload) <= (b.key, b.payload)}
+}
+
+// Worst-case and average number of effective lane-swap operations, i.e.,
+// those where the inputs differ so that the outcome of the comparison
+// matters.  The average is taken over all zero-one inputs.
+pub fn comparison_stats(net:&Network, n:u8) -> (usize, f64) {
+    let max_mask = 1u64 << n;
+    let total: usize = (0..max_mask)
+        .map(|mask| LaneArray::new(n, &LaneArrayType::Hidden(mask)))
+        .map(|x| net.count_ops(&x, |_, a, b| a.key != b.key))
+        .sum();
+    return (net.size(), total as f64 / max_mask as f64)
+}
+
+// Largest network that compare_networks() tests exhaustively.  Larger
+// networks are tested using SUMMARY_SAMPLES randomly-selected masks.
+pub const EXHAUSTIVE_LIMIT:u8 = 16;
+pub const SUMMARY_SAMPLES:u64 = 10_000;
+
+// Summary of test results for a single network.
+pub struct NetworkSummary {
+    pub name: String,
+    pub sampled: bool,
+    pub correct: bool,
+    pub contiguous: bool,
+    pub stable: bool,
+    pub inversions: usize,
+    pub meta_inversions: usize,
+    pub depth: usize,
+    pub size: usize,
+    pub avg_swaps: f64,
+    pub avg_active: f64,
+}
+
+// Metrics for ranking a list of NetworkSummary objects.
+#[derive(Clone, Copy)]
+pub enum Metric {
+    Depth,
+    Size,
+    Swaps,
+}
+
+impl Metric {
+    pub fn parse(name:&str) -> Option<Metric> {
+        match name {
+            "depth" => Some(Metric::Depth),
+            "size"  => Some(Metric::Size),
+            "swaps" => Some(Metric::Swaps),
+            _ => None,
+        }
+    }
+}
+
+// Test each network of the designated size, then rank the results:
+// correct networks first, then stable ones, then by metric.  Networks
+// larger than EXHAUSTIVE_LIMIT are sampled, and marked as such.
+pub fn compare_networks(nets:&[(&str, &Network)], n:u8, by:Metric) -> Vec<NetworkSummary> {
+    assert!(n as usize <= MAX_LANES);
+    let sampled = n > EXHAUSTIVE_LIMIT;
+    let opts = TestOptions {
+        sample: if sampled {Some(SUMMARY_SAMPLES)} else {None},
+        ..TestOptions::default()
+    };
+    let mut result = Vec::new();
+    for (name, net) in nets.iter().filter(|(_, net)| net.len == n) {
+        let mut correct = true;
+        let mut stable = true;
+        let mut cases = 0usize;
+        let mut swaps = 0usize;
+        let mut hidden = 0usize;
+        let mut active = 0usize;
+        let mut inversions = 0usize;
+        let mut meta_inversions = 0usize;
+        for (_, typ) in test_cases_with(n as usize, &opts) {
+            let x = LaneArray::new(n, &typ);
+            let result = check_case(net, &x);
+            correct = correct && result.sorted;
+            stable = stable && result.stable;
+            inversions = cmp::max(inversions, result.output.inversions());
+            meta_inversions = cmp::max(meta_inversions, result.output.meta_inversions());
+            cases += 1;
+            swaps += net.count_swaps(&x);
+            if let LaneArrayType::Hidden(_) = typ {
+                hidden += 1;
+                active += net.count_ops(&x, |_, a, b| a.key != b.key);
+            }
+        }
+        result.push(NetworkSummary {
+            name: name.to_string(),
+            sampled,
+            correct,
+            contiguous: is_correct_for_contiguous_masks(net, n),
+            stable,
+            inversions,
+            meta_inversions,
+            depth: net.depth(),
+            size: net.size(),
+            avg_swaps: swaps as f64 / cases as f64,
+            avg_active: active as f64 / hidden as f64,
+        });
+    }
+    result.sort_by(|a, b| {
+        let rank = b.correct.cmp(&a.correct)
+            .then(b.contiguous.cmp(&a.contiguous)).then(b.stable.cmp(&a.stable));
+        rank.then(match by {
+            Metric::Depth => a.depth.cmp(&b.depth),
+            Metric::Size  => a.size.cmp(&b.size),
+            Metric::Swaps => a.avg_swaps.partial_cmp(&b.avg_swaps).unwrap(),
+        })
+    });
+    return result
+}
+
+// Print a list of NetworkSummary objects as an aligned table.
+// The "inv" columns list the worst-case number of out-of-order key pairs
+// and of out-of-order metadata pairs with equal keys, for any input.
+// Sampled results are marked with "*" and explained in a footnote.
+pub fn print_summaries(summaries:&[NetworkSummary]) {
+    println!("{:<12} {:>7} {:>7} {:>7} {:>5} {:>5} {:>5} {:>5} {:>7} {:>7}",
+             "network", "correct", "contig", "stable", "inv", "m.inv",
+             "depth", "size", "swaps", "active");
+    for s in summaries.iter() {
+        let name = if s.sampled {format!("{}*", s.name)} else {s.name.clone()};
+        println!("{:<12} {:>7} {:>7} {:>7} {:>5} {:>5} {:>5} {:>5} {:>7.3} {:>7.3}",
+                 name, s.correct, s.contiguous, s.stable, s.inversions, s.meta_inversions,
+                 s.depth, s.size, s.avg_swaps, s.avg_active);
+    }
+    if summaries.iter().any(|s| s.sampled) {
+        println!("* sampled {} masks, not exhaustive", SUMMARY_SAMPLES);
+    }
+}
+
+// Declare functions defining variations on the bitonic sort algorithm.
+// https://en.wikipedia.org/wiki/Bitonic_sorter
+pub fn bitonic4a_net() -> Network {
+    // Bitonic network, original formulation
+    // https://www.inf.hs-flensburg.de/lang/algorithmen/sortieren/bitonic/bitonicen.htm
+    Network::new(4, vec![
+        vec![sw(0,1),sw(3,2)],
+        vec![sw(0,2),sw(1,3)],
+        vec![sw(0,1),sw(2,3)],
+    ])
+}
+
+pub fn bitonic4a(p0:&LaneArray) -> LaneArray {
+    assert_eq!(p0.lanes.len(), 4usize);
+    return bitonic4a_net().apply(p0)
+}
+
+pub fn bitonic4b_net() -> Network {
+    // Bitonic network, downward swaps only
+    Network::new(4, vec![
+        vec![sw(0,1),sw(2,3)],
+        vec![sw(0,3),sw(1,2)],
+        vec![sw(0,1),sw(2,3)],
+    ])
+}
+
+pub fn bitonic4b(p0:&LaneArray) -> LaneArray {
+    assert_eq!(p0.lanes.len(), 4usize);
+    return bitonic4b_net().apply(p0)
+}
+
+pub fn bitonic8a_net() -> Network {
+    // Bitonic network, original formulation
+    // https://en.wikipedia.org/wiki/Bitonic_sorter#/media/File:BitonicSort1.svg
+    Network::new(8, vec![
+        vec![sw(0,1),sw(3,2),sw(4,5),sw(7,6)],
+        vec![sw(0,2),sw(1,3),sw(7,5),sw(6,4)],
+        vec![sw(0,1),sw(2,3),sw(5,4),sw(7,6)],
+        vec![sw(0,4),sw(1,5),sw(2,6),sw(3,7)],
+        vec![sw(0,2),sw(1,3),sw(4,6),sw(5,7)],
+        vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)],
+    ])
+}
+
+pub fn bitonic8a(p0:&LaneArray) -> LaneArray {
+    assert_eq!(p0.lanes.len(), 8usize);
+    return bitonic8a_net().apply(p0)
+}
+
+pub fn bitonic8b_net() -> Network {
+    // Bitonic network, downward swaps only
+    // https://en.wikipedia.org/wiki/Bitonic_sorter#/media/File:BitonicSort.svg
+    Network::new(8, vec![
+        vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)],
+        vec![sw(0,3),sw(1,2),sw(4,7),sw(5,6)],
+        vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)],
+        vec![sw(0,7),sw(1,6),sw(2,5),sw(3,4)],
+        vec![sw(0,2),sw(1,3),sw(4,6),sw(5,7)],
+        vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)],
+    ])
+}
+
+pub fn bitonic8b(p0:&LaneArray) -> LaneArray {
+    assert_eq!(p0.lanes.len(), 8usize);
+    return bitonic8b_net().apply(p0)
+}
+
+pub fn batcher8_net() -> Network {
+    // Batcher sort, aka odd-even mergesort
+    // https://www.inf.hs-flensburg.de/lang/algorithmen/sortieren/networks/oemen.htm
+    Network::new(8, vec![
+        vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)],
+        vec![sw(0,2),sw(1,3),sw(4,6),sw(5,7)],
+        vec![sw(1,2),sw(5,6)],
+        vec![sw(0,4),sw(1,5),sw(2,6),sw(3,7)],
+        vec![sw(2,4),sw(3,5)],
+        vec![sw(1,2),sw(3,4),sw(5,6)],
+    ])
+}
+
+pub fn batcher8(p0:&LaneArray) -> LaneArray {
+    assert_eq!(p0.lanes.len(), 8usize);
+    return batcher8_net().apply(p0)
+}
+
+pub fn bubble8_net() -> Network {
+    // Bubble sort
+    // https://www.inf.hs-flensburg.de/lang/algorithmen/sortieren/networks/sortieren.htm
+    Network::new(8, vec![
+        vec![sh(0,1)],
+        vec![sh(1,2)],
+        vec![sh(0,1),sh(2,3)],
+        vec![sh(1,2),sh(3,4)],
+        vec![sh(0,1),sh(2,3),sh(4,5)],
+        vec![sh(1,2),sh(3,4),sh(5,6)],
+        vec![sh(0,1),sh(2,3),sh(4,5),sh(6,7)],
+        vec![sh(1,2),sh(3,4),sh(5,6)],
+        vec![sh(0,1),sh(2,3),sh(4,5)],
+        vec![sh(1,2),sh(3,4)],
+        vec![sh(0,1),sh(2,3)],
+        vec![sh(1,2)],
+        vec![sh(0,1)],
+    ])
+}
+
+pub fn bubble8(p0:&LaneArray) -> LaneArray {
+    assert_eq!(p0.lanes.len(), 8usize);
+    return bubble8_net().apply(p0)
+}
+
+pub fn pairwise8_net() -> Network {
+    // Pairwise sorting network
+    // https://en.wikipedia.org/wiki/Pairwise_sorting_network
+    Network::new(8, vec![
+        vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)],
+        vec![sw(0,2),sw(1,3),sw(4,6),sw(5,7)],
+        vec![sw(0,4),sw(1,5),sw(2,6),sw(3,7)],
+        vec![sw(2,4),sw(3,5)],
+        vec![sw(1,4),sw(3,6)],
+        vec![sw(1,2),sw(3,4),sw(5,6)],
+    ])
+}
+
+pub fn pairwise8(p0:&LaneArray) -> LaneArray {
+    assert_eq!(p0.lanes.len(), 8usize);
+    return pairwise8_net().apply(p0)
+}
+
+pub fn transpose8_net() -> Network {
+    // Odd-even transpose sort
+    // https://www.inf.hs-flensburg.de/lang/algorithmen/sortieren/networks/oetsen.htm
+    Network::new(8, vec![
+        vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)],
+        vec![sw(1,2),sw(3,4),sw(5,6)],
+        vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)],
+        vec![sw(1,2),sw(3,4),sw(5,6)],
+        vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)],
+        vec![sw(1,2),sw(3,4),sw(5,6)],
+        vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)],
+        vec![sw(1,2),sw(3,4),sw(5,6)],
+    ])
+}
+
+pub fn transpose8(p0:&LaneArray) -> LaneArray {
+    assert_eq!(p0.lanes.len(), 8usize);
+    return transpose8_net().apply(p0)
+}
+
+pub fn transpose8s_net() -> Network {
+    // Information-deleting analogue to "transpose8".
+    Network::new(8, vec![
+        vec![sh(0,1),sh(2,3),sh(4,5),sh(6,7)],
+        vec![sh(1,2),sh(3,4),sh(5,6)],
+        vec![sh(0,1),sh(2,3),sh(4,5),sh(6,7)],
+        vec![sh(1,2),sh(3,4),sh(5,6)],
+        vec![sh(0,1),sh(2,3),sh(4,5),sh(6,7)],
+        vec![sh(1,2),sh(3,4),sh(5,6)],
+        vec![sh(0,1),sh(2,3),sh(4,5),sh(6,7)],
+        vec![sh(1,2),sh(3,4),sh(5,6)],
+    ])
+}
+
+pub fn transpose8s(p0:&LaneArray) -> LaneArray {
+    assert_eq!(p0.lanes.len(), 8usize);
+    return transpose8s_net().apply(p0)
+}
+
+pub fn transpose3s_net() -> Network {
+    // Test variants of "transpose8s" with unusual sizes.
+    Network::new(3, vec![
+        vec![sh(0,1)],
+        vec![sh(1,2)],
+        vec![sh(0,1)],
+    ])
+}
+
+pub fn transpose3s(p0:&LaneArray) -> LaneArray {
+    assert_eq!(p0.lanes.len(), 3usize);
+    return transpose3s_net().apply(p0)
+}
+
+pub fn transpose5s_net() -> Network {
+    // Test variants of "transpose8s" with unusual sizes.
+    Network::new(5, vec![
+        vec![sh(0,1),sh(2,3)],
+        vec![sh(1,2),sh(3,4)],
+        vec![sh(0,1),sh(2,3)],
+        vec![sh(1,2),sh(3,4)],
+        vec![sh(0,1),sh(2,3)],
+    ])
+}
+
+pub fn transpose5s(p0:&LaneArray) -> LaneArray {
+    assert_eq!(p0.lanes.len(), 5usize);
+    return transpose5s_net().apply(p0)
+}
+
+pub fn transpose6s_net() -> Network {
+    // Test variants of "transpose8s" with unusual sizes.
+    Network::new(6, vec![
+        vec![sh(0,1),sh(2,3),sh(4,5)],
+        vec![sh(1,2),sh(3,4)],
+        vec![sh(0,1),sh(2,3),sh(4,5)],
+        vec![sh(1,2),sh(3,4)],
+        vec![sh(0,1),sh(2,3),sh(4,5)],
+        vec![sh(1,2),sh(3,4)],
+    ])
+}
+
+pub fn transpose6s(p0:&LaneArray) -> LaneArray {
+    assert_eq!(p0.lanes.len(), 6usize);
+    return transpose6s_net().apply(p0)
+}
+
+// Constant tables for the smallest classic networks, for use without any
+// runtime construction or allocation (e.g., in embedded code).  Each table
+// lists the stages in order, each as a list of (min_out, max_out) pairs.
+pub type StageTable = &'static [&'static [(u8, u8)]];
+
+pub const BITONIC4A_TABLE: StageTable = &[
+    &[(0,1),(3,2)],
+    &[(0,2),(1,3)],
+    &[(0,1),(2,3)],
+];
+
+pub const BITONIC8A_TABLE: StageTable = &[
+    &[(0,1),(3,2),(4,5),(7,6)],
+    &[(0,2),(1,3),(7,5),(6,4)],
+    &[(0,1),(2,3),(5,4),(7,6)],
+    &[(0,4),(1,5),(2,6),(3,7)],
+    &[(0,2),(1,3),(4,6),(5,7)],
+    &[(0,1),(2,3),(4,5),(6,7)],
+];
+
+pub const BATCHER8_TABLE: StageTable = &[
+    &[(0,1),(2,3),(4,5),(6,7)],
+    &[(0,2),(1,3),(4,6),(5,7)],
+    &[(1,2),(5,6)],
+    &[(0,4),(1,5),(2,6),(3,7)],
+    &[(2,4),(3,5)],
+    &[(1,2),(3,4),(5,6)],
+];
+
+// Total number of lane-swap operations in a table, at compile time.
+pub const fn table_size(table:StageTable) -> usize {
+    let mut total = 0;
+    let mut k = 0;
+    while k < table.len() {
+        total += table[k].len();
+        k += 1;
+    }
+    return total
+}
+
+pub const BATCHER8_SIZE: usize = table_size(BATCHER8_TABLE);
+
+// Sort a slice of keys in place using a constant table, without allocation.
+pub fn sort_with_table(table:StageTable, keys:&mut [u64]) {
+    for stage in table.iter() {
+        for (lo, hi) in stage.iter() {
+            let (lo, hi) = (*lo as usize, *hi as usize);
+            if keys[lo] > keys[hi] {keys.swap(lo, hi);}
+        }
+    }
+}
+
+// Sort a power-of-two slice of keys in place using Batcher's bitonic
+// sorter, without allocation.  The comparators depend only on the loop
+// indices, so this is a fixed network of log2(n)*(log2(n)+1)/2 stages,
+// e.g., 15 stages for 32 keys where periodic_network() needs 25.
+pub fn sort_bitonic(keys:&mut [u64]) {
+    let n = keys.len();
+    assert!(n.is_power_of_two());
+    let mut k = 2;
+    while k <= n {
+        let mut j = k / 2;
+        while j > 0 {
+            for lo in 0..n {
+                let hi = lo ^ j;
+                if hi <= lo {continue}
+                // Alternate blocks of size k sort in opposite directions.
+                let (a, b) = if lo & k == 0 {(lo, hi)} else {(hi, lo)};
+                if keys[a] > keys[b] {keys.swap(a, b);}
+            }
+            j /= 2;
+        }
+        k *= 2;
+    }
+}
+
+// Largest slice that sort_slice() sorts using a network.
+pub const SORT_SLICE_LIMIT: usize = 32;
+
+// Sort a slice of keys in place, using a sorting network for small slices.
+// Up to 8 keys use a constant table; larger slices up to SORT_SLICE_LIMIT
+// use sort_bitonic().  In either case, the keys are padded to the network
+// size with u64::MAX in a stack buffer, which sorts after every real key.
+// Larger slices fall back to slice::sort_unstable().
+pub fn sort_slice(keys:&mut [u64]) {
+    let n = keys.len();
+    if n <= 1 {return}
+    if n <= 8 {
+        let (table, len) = if n <= 4 {(BITONIC4A_TABLE, 4)} else {(BATCHER8_TABLE, 8)};
+        let mut buf = [u64::MAX; 8];
+        buf[..n].copy_from_slice(keys);
+        sort_with_table(table, &mut buf[..len]);
+        keys.copy_from_slice(&buf[..n]);
+    } else if n <= SORT_SLICE_LIMIT {
+        let len = n.next_power_of_two();
+        let mut buf = [u64::MAX; SORT_SLICE_LIMIT];
+        buf[..n].copy_from_slice(keys);
+        sort_bitonic(&mut buf[..len]);
+        keys.copy_from_slice(&buf[..n]);
+    } else {
+        keys.sort_unstable();
+    }
+}
+
+// List of all built-in networks, with labels.
+pub fn builtin_networks() -> Vec<(&'static str, Network)> {
+    vec![("bitonic4a",  bitonic4a_net()),
+         ("bitonic4b",  bitonic4b_net()),
+         ("bitonic8a",  bitonic8a_net()),
+         ("bitonic8b",  bitonic8b_net()),
+         ("batcher8",   batcher8_net()),
+         ("pairwise8",  pairwise8_net()),
+         ("transpose8", transpose8_net()),
+         ("bubble8",    bubble8_net()),
+         ("transpose8s", transpose8s_net()),
+         ("transpose3s", transpose3s_net()),
+         ("transpose5s", transpose5s_net()),
+         ("transpose6s", transpose6s_net())]
+}
+
+// Look up a built-in network by name.
+pub fn builtin_network(name:&str) -> Option<Network> {
+    builtin_networks().into_iter()
+        .find(|(lbl, _)| *lbl == name)
+        .map(|(_, net)| net)
+}
+
+// Unit tests for the networks and the functions that analyze them.
+// Run with "cargo test"; the default run of the binary reports only the
+// stability of each built-in sorting function.
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BinaryHeap;
+
+    // Confirm that the reversed network sorts in descending order, i.e.,
+    // that reversing its output lane order matches the original network.
+    #[test]
+    fn test_reversed() {
+        let net = batcher8_net();
+        let rev = reversed(&net);
+        for (_, typ) in test_cases(net.len as usize) {
+            let x = LaneArray::new(net.len, &typ);
+            let mut bwd = check_case(&rev, &x).output.keys();
+            bwd.reverse();
+            assert_eq!(check_case(&net, &x).output.keys(), bwd, "x = {}", x);
+        }
+    }
+
+    // Confirm formatting of empty, single-element, and multi-element arrays.
+    #[test]
+    fn test_display() {
+        let cases = [(0u8, "()"), (1u8, "(0)"), (3u8, "(0, 1, 2)")];
+        for (len, expected) in cases.iter() {
+            assert_eq!(format!("{}", LaneArray::new(*len, &LaneArrayType::Simple(0))), *expected);
+        }
+    }
+
+    // Confirm that every built-in network passes the zero-one test.
+    #[test]
+    fn test_zero_one() {
+        for (name, net) in builtin_networks().iter() {
+            assert!(is_sorting_network(net, None), "{}", name);
+        }
+    }
+
+    // Confirm that every built-in network sorts under every key encoding,
+    // including the compaction networks, whose placeholder keys differ from
+    // the disabled-lane keys of the MaxValue and Random encodings.
+    #[test]
+    fn test_encodings() {
+        let names = ["simple", "hidden", "max", "random"];
+        let opts = TestOptions {
+            encodings: names.iter().map(|name| LaneArrayType::parse(name).unwrap()).collect(),
+            ..TestOptions::default()
+        };
+        for (name, net) in builtin_networks().iter() {
+            for (mask, typ) in test_cases_with(net.len as usize, &opts) {
+                let x = LaneArray::new(net.len, &typ);
+                assert!(check_case(net, &x).sorted, "{}: mask = {:#x}, x = {}", name, mask, x);
+            }
+        }
+        let y = LaneArray::from_keys(&[1, u64::MAX, PENALTY]);
+        assert!(y.is_sorted_valid() && !y.is_sorted_key());
+        assert!(!LaneArray::from_keys(&[2, 1, PENALTY]).is_sorted_valid());
+    }
+
+    // Confirm that a network using the KeyThenMeta ordering preserves order,
+    // even if the same network is unstable with the default ordering.
+    #[test]
+    fn test_comparator() {
+        let net = batcher8_net();
+        for (_, typ) in test_cases(net.len as usize) {
+            let x = LaneArray::new(net.len, &typ);
+            let result = check_case_fn(|x| net.apply_by::<KeyThenMeta, _>(x), &x);
+            assert!(result.sorted && result.stable, "x = {}, y = {}", x, result.output);
+        }
+    }
+
+    // Confirm that removing duplicate lane-swap operations from a network
+    // produces the same output for every zero-one input.
+    #[test]
+    fn test_dedup() {
+        let net = batcher8_net();
+        let mut dup = net.clone();
+        dup.stages.push(net.stages.last().unwrap().clone());
+        let (result, removed) = dedup_comparators(&dup);
+        for mask in 0..1u64 << net.len {
+            let x = LaneArray::new(net.len, &LaneArrayType::Hidden(mask));
+            assert_eq!(dup.apply(&x).keys(), result.apply(&x).keys(), "x = {}", x);
+        }
+        assert_eq!(removed, net.stages.last().unwrap().len());
+        assert_eq!(dup.size() - result.size(), removed);
+        assert_eq!(dedup_comparators(&net).1, 0);
+    }
+
+    // Confirm that a network sorts a specific input vector, and that the
+    // metadata of each output lane identifies its original index.
+    #[test]
+    fn test_from_keys() {
+        let keys = [50u64, 30, 70, 10, 0, 20, 60, 40];
+        let y = batcher8_net().apply(&LaneArray::from_keys(&keys));
+        let meta: Vec<u64> = y.lanes.iter().map(|lane| lane.payload).collect();
+        let mut sorted = keys.to_vec();
+        sorted.sort_unstable();
+        assert_eq!(y.keys(), sorted);
+        assert_eq!(meta, vec![4, 3, 5, 1, 7, 0, 6, 2]);
+    }
+
+    // Confirm that the routing map for every input is a permutation, and
+    // that applying it to the input keys reproduces the network output.
+    #[test]
+    fn test_routing() {
+        let net = batcher8_net();
+        for mask in 0..1u64 << net.len {
+            let x = LaneArray::new(net.len, &LaneArrayType::Simple(mask));
+            let route = routing(&net, &x);
+            let mut check = route.clone();
+            check.sort_unstable();
+            let keys: Vec<u64> = route.iter().map(|n| x.lanes[*n].key).collect();
+            assert_eq!(check, (0..net.len as usize).collect::<Vec<usize>>(), "x = {}", x);
+            assert_eq!(keys, net.apply(&x).keys(), "x = {}", x);
+        }
+    }
+
+    // Confirm that a network with group size 1 is identical to the usual
+    // case, and that groups of two lanes stay intact through the network.
+    #[test]
+    fn test_grouped() {
+        let net = batcher8_net();
+        for mask in 0..1u64 << net.len {
+            let x = LaneArray::new(net.len, &LaneArrayType::Simple(mask));
+            let y1 = net.apply(&x);
+            let y2 = net.apply_grouped(&x, 1);
+            let meta1: Vec<u64> = y1.lanes.iter().map(|lane| lane.payload).collect();
+            let meta2: Vec<u64> = y2.lanes.iter().map(|lane| lane.payload).collect();
+            assert_eq!(y1.keys(), y2.keys(), "x = {}", x);
+            assert_eq!(meta1, meta2, "x = {}", x);
+        }
+        let mut rng = Rng::new(SEED);
+        for _ in 0..100 {
+            let keys: Vec<u64> = (0..2*net.len).map(|_| rng.next() % 16).collect();
+            let y = net.apply_grouped(&LaneArray::from_keys(&keys), 2);
+            let heads: Vec<u64> = y.lanes.iter().step_by(2).map(|lane| lane.key).collect();
+            let intact = y.lanes.chunks(2).all(|pair|
+                pair[0].payload % 2 == 0 && pair[1].payload == pair[0].payload + 1);
+            assert!(intact && heads.windows(2).all(|w| w[0] <= w[1]), "y = {}", y);
+        }
+    }
+
+    // Confirm that a size mismatch is reported as an error, not a panic.
+    #[test]
+    fn test_size_error() {
+        let net = batcher8_net();
+        let short = LaneArray::new(net.len - 1, &LaneArrayType::Simple(0));
+        let exact = LaneArray::new(net.len, &LaneArrayType::Simple(0));
+        match net.try_apply(&short) {
+            Err(SizeError {expected, actual}) => {
+                assert_eq!(expected, net.len as usize);
+                assert_eq!(actual + 1, expected);
+            },
+            Ok(_) => panic!("Size mismatch not detected."),
+        }
+        assert!(net.try_apply(&exact).is_ok());
+    }
+
+    // Confirm the classification of every built-in sorting function.
+    #[test]
+    fn test_classify() {
+        let table: [(u8, SortFn, Stability); 12] = [
+            (4, bitonic4a,   Stability::Unstable),
+            (4, bitonic4b,   Stability::Unstable),
+            (8, bitonic8a,   Stability::Unstable),
+            (8, bitonic8b,   Stability::Unstable),
+            (8, batcher8,    Stability::Unstable),
+            (8, bubble8,     Stability::Stable),
+            (8, pairwise8,   Stability::Unstable),
+            (8, transpose8,  Stability::Stable),
+            (8, transpose8s, Stability::Stable),
+            (3, transpose3s, Stability::Stable),
+            (5, transpose5s, Stability::Stable),
+            (6, transpose6s, Stability::Stable)];
+        for (len, sortfn, expected) in table.iter() {
+            assert_eq!(classify_fn(*len, sortfn), *expected);
+        }
+        let incorrect = Network::new(4, vec![vec![sw(0,1),sw(2,3)]]);
+        assert_eq!(classify(&incorrect, 4), Stability::Incorrect);
+    }
+
+    // Confirm that the schedule has one cycle per stage, as does the ROM.
+    #[test]
+    fn test_schedule() {
+        let net = batcher8_net();
+        let rom = to_verilog_rom(&net, "test");
+        let words = rom.lines().filter(|line| line.contains("test_rom[")).count();
+        assert_eq!(schedule(&net).len(), net.depth());
+        assert_eq!(words, net.depth());
+        for empty in [Network::new(0, vec![]), Network::new(4, vec![])].iter() {
+            let rom = to_verilog_rom(empty, "empty");
+            assert!(rom.contains("0 cycles"));
+            assert!(!rom.contains("reg "));
+        }
+    }
+
+    // Confirm that the stage widths account for every lane-swap operation,
+    // and that the widest stage of batcher8 has one operation per lane pair.
+    #[test]
+    fn test_widths() {
+        let net = batcher8_net();
+        let widths = stage_widths(&net);
+        assert_eq!(widths, vec![4, 4, 2, 4, 2, 3]);
+        assert_eq!(widths.iter().sum::<usize>(), net.size());
+        assert_eq!(max_parallelism(&net), 4);
+        assert_eq!(max_parallelism(&Network::new(4, Vec::new())), 0);
+    }
+
+    // Confirm that a network sorts by key then metadata when ties are resolved
+    // using with_meta_tiebreak(), even if it fails that check otherwise.
+    #[test]
+    fn test_tiebreak() {
+        let net = batcher8_net();
+        let mut plain = 0u64;
+        for (_, typ) in test_cases(net.len as usize) {
+            let x = LaneArray::new(net.len, &typ);
+            let y = check_case_fn(|x| net.apply(&x.with_meta_tiebreak()).without_meta_tiebreak(), &x).output;
+            assert!(y.is_sorted_key_then_meta(), "x = {}, y = {}", x, y);
+            if !check_case(&net, &x).output.is_sorted_key_then_meta() {plain += 1;}
+        }
+        assert!(plain > 0);
+    }
+
+    // Confirm that a key too large to shift left is rejected, rather than
+    // silently truncated so that it sorts first.
+    #[test]
+    #[should_panic(expected = "Key too large")]
+    fn test_tiebreak_overflow() {
+        LaneArray::from_keys(&[1 << 50, 1]).with_meta_tiebreak();
+    }
+
+    // Confirm that the builder reproduces a literal network, and that it
+    // rejects a network that reuses a lane within the same stage or that
+    // compares a lane to itself.
+    #[test]
+    fn test_builder() {
+        let built = NetworkBuilder::new(8)
+            .compare(0,1).compare(2,3).compare(4,5).compare(6,7)
+            .compare(0,2).compare(1,3).compare(4,6).compare(5,7)
+            .compare(1,2).compare(5,6).add_stage()
+            .compare(0,4).compare(1,5).compare(2,6).compare(3,7)
+            .compare(2,4).compare(3,5)
+            .compare(1,2).compare(3,4).compare(5,6)
+            .build();
+        assert!(built.ok() == Some(batcher8_net()));
+        assert!(NetworkBuilder::new(4).compare(0,1).compare(3,3).build().is_err());
+        assert!(LaneSwap::new(3,2) == Ok(sw(3,2)));
+        assert!(LaneSwap::new(3,3).is_err());
+    }
+
+    // Confirm that the built-in comparator networks are permutations, and
+    // that a stage with a duplicated output lane is rejected, both directly
+    // and by the builder.
+    #[test]
+    fn test_is_permutation() {
+        let dup = vec![sw(0,1), sw(2,1)];
+        let mut builder = NetworkBuilder::new(4).compare(0,1);
+        builder.stages[0].push(sw(2,1));
+        for (name, net) in builtin_networks().iter() {
+            let shifts = net.comparators().any(|(_, op)| op.2 == Mode::Shift);
+            assert_eq!(is_permutation_network(net), !shifts, "{}", name);
+        }
+        assert!(!is_permutation_network(&Network::new(4, vec![dup])));
+        assert!(!is_permutation_network(&Network::new(2, vec![vec![LaneSwap(1,1,Mode::Swap)]])));
+        assert!(!is_permutation_network(&transpose8s_net()));
+        assert!(builder.build().is_err());
+    }
+
+    // Confirm the critical path for a network where every stage depends on
+    // the previous stage, and for one where the stages are independent.
+    #[test]
+    fn test_critical_path() {
+        let sparse = Network::new(4, vec![vec![sw(0,1)], vec![sw(2,3)]]);
+        let chain = transpose8_net();
+        assert_eq!(critical_path(&chain), chain.depth());
+        assert_eq!(critical_path(&sparse), 1);
+    }
+
+    // Confirm that the timing estimate grows with data width, scales with the
+    // critical path, and is zero for a network with no lane-swap operations.
+    #[test]
+    fn test_timing() {
+        let net = batcher8_net();
+        let delays: Vec<f64> = (1..=64).map(|w| timing_estimate(&net, w, 10.0)).collect();
+        let empty = Network::new(8, Vec::new());
+        assert!(delays.windows(2).all(|w| w[0] < w[1]), "{:?}", delays);
+        assert_eq!(timing_estimate(&net, 16, 0.0), critical_path(&net) as f64 * COMPARATOR_BASE_PS);
+        assert!(timing_estimate(&transpose8_net(), 16, 10.0) > delays[15]);
+        assert_eq!(timing_estimate(&empty, 16, 10.0), 0.0);
+    }
+
+    // Confirm that both ascending and crossed-output lane-swap operations
+    // write the smaller key to min_out() and the larger key to max_out().
+    #[test]
+    fn test_min_max() {
+        for keys in [[3u64, 5], [5, 3]].iter() {
+            let x = LaneArray::from_keys(keys);
+            for op in [sw(0,1), sw(1,0)].iter() {
+                let y = x.swap(&vec![*op]);
+                assert_eq!(y.lanes[op.min_out()].key, 3, "y = {}", y);
+                assert_eq!(y.lanes[op.max_out()].key, 5, "y = {}", y);
+            }
+        }
+    }
+
+    // Confirm that the testbench instantiates the module by name, and that
+    // its port widths match the module.
+    #[test]
+    fn test_verilog() {
+        let net = batcher8_net();
+        let dut = to_verilog(&net, "batcher8", 16);
+        let tb = to_verilog_testbench(&net, "batcher8", 16);
+        assert!(dut.contains("module batcher8 ("));
+        assert!(dut.contains("input  wire [127:0] in_data"));
+        assert!(tb.contains("batcher8 dut ("));
+        assert!(tb.contains("reg  [127:0] in_data;"));
+        assert!(tb.contains("wire [127:0] out_data;"));
+    }
+
+    // Confirm that shift and exchange operations generate their own logic
+    // and glyphs, and that the shift networks are available by name.
+    #[test]
+    fn test_modes() {
+        let net = builtin_network("transpose3s").unwrap();
+        let v = to_verilog(&net, "transpose3s", 16);
+        let tb = to_verilog_testbench(&net, "transpose3s", 16);
+        assert!(v.contains("assign s1_0 = (s0_0 < 256) ? s0_0 : s0_1;"));
+        assert!(v.contains("assign s1_1 = (s0_0 < 256) ? s0_1 : 256;"));
+        assert!(!v.contains("<="));
+        assert!(tb.contains("? 256 : lane;"));
+        assert!(to_verilog_rom(&net, "t").contains("mode[1:0]"));
+        assert!(render_ascii(&net).contains('#') && !render_ascii(&net).contains('*'));
+        assert!(to_svg(&net).contains("<rect"));
+        let swap = permutation_network(&[1, 0]);
+        assert!(to_verilog(&swap, "swap", 8).contains("assign s1_0 = s0_1;"));
+        let tb = to_verilog_testbench(&permutation_network(&[2, 0, 1]), "perm", 8);
+        assert!(tb.contains("if (out_data[0 +: 8] != 2) begin"));
+        assert!(tb.contains("if (out_data[8 +: 8] != 0) begin"));
+        assert!(tb.contains("if (out_data[16 +: 8] != 1) begin"));
+        assert!(!tb.contains(">"));
+        assert_eq!(render_ascii(&swap), " 0 -x-\n 1 -x-\n");
+        for name in ["bubble8", "transpose8s", "transpose5s", "transpose6s"].iter() {
+            assert!(builtin_network(name).unwrap().comparators().all(|(_, op)| op.2 == Mode::Shift));
+        }
+    }
+
+    // Confirm that comparators() visits every lane-swap operation once,
+    // in execution order.
+    #[test]
+    fn test_comparators() {
+        for (name, net) in builtin_networks().iter() {
+            let stages: Vec<usize> = net.comparators().map(|(k, _)| k).collect();
+            assert_eq!(stages.len(), net.size(), "{}", name);
+            assert!(stages.windows(2).all(|w| w[0] <= w[1]), "{}", name);
+        }
+    }
+
+    // Confirm that idle lanes are drawn distinctly in both renderers.
+    #[test]
+    fn test_render() {
+        let net = transpose8_net();
+        let idle: usize = (0..net.depth()).map(|k| net.idle_lanes(k).len()).sum();
+        let ascii = render_ascii(&net);
+        let svg = to_svg(&net);
+        assert!(idle > 0);
+        assert_eq!(ascii.matches(". ").count() + ascii.matches(".\n").count(), idle, "{}", ascii);
+        assert_eq!(svg.matches("stroke-dasharray").count(), idle);
+    }
+
+    // Golden files for the generated artifacts, which pin the exact output of
+    // the export functions so that refactoring cannot change it unnoticed.
+    // After an intentional change, review and regenerate each file using the
+    // matching subcommand, e.g., "bitonic verilog batcher8 > golden/batcher8.v".
+    const GOLDEN: [(&str, &str, &str); 8] = [
+        ("batcher8",  "v",     include_str!("../golden/batcher8.v")),
+        ("batcher8",  "rom.v", include_str!("../golden/batcher8_rom.v")),
+        ("batcher8",  "svg",   include_str!("../golden/batcher8.svg")),
+        ("batcher8",  "txt",   include_str!("../golden/batcher8.txt")),
+        ("bitonic8a", "v",     include_str!("../golden/bitonic8a.v")),
+        ("bitonic8a", "rom.v", include_str!("../golden/bitonic8a_rom.v")),
+        ("bitonic8a", "svg",   include_str!("../golden/bitonic8a.svg")),
+        ("bitonic8a", "txt",   include_str!("../golden/bitonic8a.txt")),
+    ];
+
+    // Confirm that every generated artifact matches its golden file.
+    #[test]
+    fn test_golden() {
+        for (name, ext, expected) in GOLDEN.iter() {
+            let net = builtin_network(name).unwrap();
+            let actual = match *ext {
+                "v"     => to_verilog(&net, name, 16) + &to_verilog_testbench(&net, name, 16),
+                "rom.v" => to_verilog_rom(&net, name),
+                "svg"   => to_svg(&net),
+                _       => render_ascii(&net),
+            };
+            assert_eq!(actual, *expected, "{}.{}", name, ext);
+        }
+    }
+
+    // Confirm that swap frequencies are valid probabilities, and that they
+    // match a brute-force count using the state before each stage.
+    #[test]
+    fn test_heatmap() {
+        let net = batcher8_net();
+        let freq = swap_frequency(&net, net.len);
+        let max_mask = 1u64 << net.len;
+        let mut brute = Vec::new();
+        for (k, stage) in net.stages.iter().enumerate() {
+            let prefix = Network::new(net.len, net.stages[..k].to_vec());
+            for op in stage.iter() {
+                let count = (0..max_mask)
+                    .map(|mask| prefix.apply(&LaneArray::new(net.len, &LaneArrayType::Hidden(mask))))
+                    .filter(|p| p.lanes[op.min_out()].key > p.lanes[op.max_out()].key)
+                    .count();
+                brute.push(count as f64 / max_mask as f64);
+            }
+        }
+        assert!(freq.iter().all(|f| (0.0..=1.0).contains(f)), "{:?}", freq);
+        assert_eq!(freq, brute);
+        assert_eq!(to_svg_heatmap(&net).matches("rgb(").count(), 3 * net.size());
+        // A fixed exchange always swaps, and a shift moves up a valid lane
+        // whenever the lane bound for min_out() is disabled.
+        let swap = permutation_network(&[1, 0]);
+        assert_eq!(swap_frequency(&swap, 2), vec![1.0]);
+        assert_eq!(swap.count_swaps(&LaneArray::from_keys(&[3, 9])), 1);
+        let shift = Network::new(2, vec![vec![sh(0, 1)]]);
+        assert_eq!(swap_frequency(&shift, 2), vec![0.5]);
+    }
+
+    // Confirm comparison statistics for a single lane-swap, which is
+    // effective for two of the four zero-one inputs.
+    #[test]
+    fn test_stats() {
+        let net = Network::new(2, vec![vec![sw(0,1)]]);
+        assert_eq!(comparison_stats(&net, 2), (1, 0.5));
+    }
+
+    // Compare StreamingTopK against a heap-based reference on random streams.
+    #[test]
+    fn test_topk() {
+        let mut rng = Rng::new(SEED);
+        for trial in 0..100 {
+            let k = 1 + trial % 8;
+            let mut topk = StreamingTopK::new(k).unwrap();
+            let mut heap = BinaryHeap::new();
+            for _ in 0..trial {
+                let value = rng.next() % 1000;
+                topk.push(value);
+                heap.push(value);
+                if heap.len() > k {heap.pop();}
+            }
+            assert_eq!(topk.finish(), heap.into_sorted_vec());
+        }
+        assert!(StreamingTopK::new(254).is_ok());
+        assert!(StreamingTopK::new(255).is_err());
+        assert!(StreamingTopK::new(1000).is_err());
+    }
+
+    // Confirm that splitting and reordering stages does not change the
+    // canonical form of a network, but changing an operation does.
+    #[test]
+    fn test_canonical() {
+        let net = batcher8_net();
+        let mut split = Network::new(net.len, Vec::new());
+        for stage in net.stages.iter() {
+            for op in stage.iter().rev() {
+                split.stages.push(vec![*op]);
+            }
+        }
+        let mut other = net.clone();
+        other.stages[0][0] = sw(other.stages[0][0].1, other.stages[0][0].0);
+        assert!(structurally_equal(&net, &split));
+        assert!(!structurally_equal(&net, &other));
+    }
+
+    // Confirm that shift operations never lose or duplicate a valid lane,
+    // for every mask and every information-deleting network.
+    #[test]
+    fn test_shift_checked() {
+        let nets = [bubble8_net(), transpose8s_net(),
+                    transpose3s_net(), transpose5s_net(), transpose6s_net()];
+        for net in nets.iter() {
+            for (_, typ) in test_cases(net.len as usize) {
+                let mut p = LaneArray::new(net.len, &typ);
+                for stage in net.stages.iter() {
+                    p = p.shift_checked(stage);
+                }
+            }
+        }
+    }
+
+    // Confirm that a hybrid network, which sorts pairs of lanes and then
+    // compacts the valid lanes, matches separate calls to swap() and shift().
+    #[test]
+    fn test_hybrid() {
+        let net = Network::new(4, vec![
+            vec![sw(0,1), sw(2,3)],
+            vec![sh(1,2)],
+            vec![sh(0,1), sh(2,3)],
+            vec![sh(1,2)],
+        ]);
+        for mask in 0..16u64 {
+            let x = LaneArray::new(4, &LaneArrayType::Random(mask));
+            let mut y = x.swap(&net.stages[0]);
+            for stage in net.stages[1..].iter() {y = y.shift(stage);}
+            let z = net.apply(&x);
+            let valid = z.lanes.iter().filter(|lane| lane.key < PENALTY).count();
+            assert_eq!(y.keys(), z.keys(), "x = {}", x);
+            assert_eq!(valid, (!mask & 15).count_ones() as usize, "x = {}", x);
+            assert!(z.lanes[..valid].iter().all(|lane| lane.key < PENALTY), "z = {}", z);
+        }
+    }
+
+    // Confirm that arbitrary payloads stay attached to their keys, using
+    // records with a string payload derived from each key.
+    #[test]
+    fn test_records() {
+        let net = batcher8_net();
+        let mut rng = Rng::new(SEED);
+        for _ in 0..1000 {
+            let records: Vec<(u64, String)> = (0..net.len)
+                .map(|_| rng.next() % 16)
+                .map(|key| (key, format!("record-{}", key))).collect();
+            let y = net.apply(&LaneArray::from_records(records));
+            assert!(y.lanes.iter().all(|lane| lane.payload == format!("record-{}", lane.key)));
+            assert!(y.lanes.windows(2).all(|w| w[0].key <= w[1].key));
+        }
+    }
+
+    // Confirm that a broken network yields the same counterexample and the
+    // same trace every time it is searched with the same seed.
+    #[test]
+    fn test_reproduce() {
+        let net = batcher8_net();
+        let mut bad = net.clone();
+        bad.stages[0].pop();
+        let (seed, first) = find_counterexample(&bad, SEED, 1000).expect("No counterexample found.");
+        let again = find_counterexample(&bad, SEED, 1000).expect("No counterexample found.");
+        assert_eq!((seed, first.clone()), again);
+        assert_eq!(find_counterexample(&bad, seed, 1), Some((seed, first.clone())));
+        let again = again.1;
+        assert_eq!(reproduce(&bad, &first), reproduce(&bad, &again));
+        assert!(reproduce(&bad, &first).ends_with("NOT SORTED\n"));
+        assert!(find_counterexample(&net, SEED, 1000).is_none());
+    }
+
+    // Confirm that compaction only moves operations, and that replacing an
+    // operation is reported as one addition and one removal.
+    #[test]
+    fn test_diff() {
+        let net = batcher8_net();
+        let moved = diff(&net, &compact(&net));
+        let mut edit = net.clone();
+        edit.stages[0][0] = sw(edit.stages[0][0].1, edit.stages[0][0].0);
+        let changed = diff(&net, &edit);
+        let colored = render_ascii_with(&changed.overlay(&edit), |k, op| changed.color(k, op));
+        assert!(moved.added.is_empty() && moved.removed.is_empty() && !moved.moved.is_empty());
+        assert!(changed.added == vec![(0, edit.stages[0][0])]);
+        assert!(changed.removed == vec![(0, net.stages[0][0])]);
+        assert!(changed.moved.is_empty());
+        assert!(colored.contains("\x1b[32m") && colored.contains("\x1b[31m"));
+    }
+
+    // Confirm that the search finds a valid network of the known optimal size.
+    #[test]
+    fn test_search() {
+        for (n, size) in [(4, 5), (5, 9)].iter() {
+            let net = search_optimal(*n, *size).expect("No network found.");
+            assert_eq!(net.size(), *size);
+            assert!(is_sorting_network(&net, None));
+        }
+    }
+
+    // Confirm that a selection network finds the smallest keys, and that
+    // the minimum settles earlier than the other selected outputs.
+    #[test]
+    fn test_settled() {
+        let net = select_network(8, 3);
+        for mask in 0..256u64 {
+            let y = net.apply(&LaneArray::new(8, &LaneArrayType::Simple(mask)));
+            let mut keys = LaneArray::new(8, &LaneArrayType::Simple(mask)).keys();
+            keys.sort();
+            assert_eq!(y.keys()[..3], keys[..3]);
+        }
+        let settled = earliest_settled(&net, 8);
+        assert!(settled[0] < settled[1] && settled[1] < settled[2], "{:?}", settled);
+    }
+
+    // Confirm that a network can be correct for contiguous masks only, and
+    // that the summary reports it as such.
+    #[test]
+    fn test_contiguous() {
+        let net = Network::new(4, vec![
+            vec![sw(0,2)],
+            vec![sw(1,2)],
+            vec![sw(1,3)],
+            vec![sw(0,1), sw(2,3)],
+        ]);
+        let summary = compare_networks(&[("packing", &net)], 4, Metric::Size);
+        assert!(is_correct_for_contiguous_masks(&net, 4));
+        assert!(!is_sorting_network(&net, None));
+        assert!(summary[0].contiguous && !summary[0].correct);
+        assert!(is_correct_for_contiguous_masks(&batcher8_net(), 8));
+    }
+
+    // Confirm that networks survive a round-trip through the text format,
+    // and that malformed text is rejected.
+    #[test]
+    fn test_parse() {
+        for net in [batcher8_net(), transpose8s_net()].iter() {
+            assert!(parse_network(&to_text(net)).ok().as_ref() == Some(net));
+        }
+        let inferred = parse_network("0:1 2:3  # first\n\n1:2\n");
+        assert!(inferred.is_ok_and(|net| net.len == 4 && net.depth() == 2));
+        for text in ["0:0", "0:1 1:2", "0:x", "lanes 2\n0:2", "lanes many", "lanes 64\n0:1"].iter() {
+            assert!(parse_network(text).is_err(), "{}", text);
+        }
+    }
+
+    // Confirm that sampled masks are distinct, and that a sample larger than
+    // the number of masks is clamped to the exhaustive test.
+    #[test]
+    fn test_sample_masks() {
+        let masks: HashSet<u64> = sample_masks(8, Some(100)).collect();
+        assert_eq!(masks.len(), 100);
+        assert!(masks.iter().all(|mask| *mask < 256));
+        assert_eq!(sample_count(4, Some(100)), 16);
+        assert_eq!(sample_masks(4, Some(100)).collect::<Vec<u64>>(), (0..16).collect::<Vec<u64>>());
+        assert_eq!(sample_count(4, None), 16);
+        assert!(is_sorting_network(&periodic_network(32, 5), Some(1000)));
+    }
+
+    // Confirm that a network too large for exhaustive testing is sampled and
+    // marked as such, and that a network with one round too few is caught.
+    #[test]
+    fn test_sampled_summary() {
+        let net = periodic_network(32, 5);
+        let bad = periodic_network(32, 4);
+        let summary = compare_networks(&[("periodic32", &net), ("bad", &bad)], 32, Metric::Size);
+        assert!(summary.iter().all(|s| s.sampled));
+        assert!(summary[0].name == "periodic32" && summary[0].correct);
+        assert!(!summary[1].correct);
+        assert!(!compare_networks(&[("batcher8", &batcher8_net())], 8, Metric::Size)[0].sampled);
+    }
+
+    // Confirm that the constant tables match the runtime networks, and that
+    // sorting in place with a table matches Network::apply().
+    #[test]
+    fn test_tables() {
+        let tables = [(4, BITONIC4A_TABLE, bitonic4a_net()),
+                      (8, BITONIC8A_TABLE, bitonic8a_net()),
+                      (8, BATCHER8_TABLE,  batcher8_net())];
+        let mut rng = Rng::new(SEED);
+        assert_eq!(BATCHER8_SIZE, batcher8_net().size());
+        for (len, table, net) in tables.iter() {
+            assert!(Network::from_table(*len, table) == *net);
+            assert_eq!(table_size(table), net.size());
+            for _ in 0..100 {
+                let mut keys: Vec<u64> = (0..*len).map(|_| rng.next() % 16).collect();
+                let expected = net.apply(&LaneArray::from_keys(&keys)).keys();
+                sort_with_table(table, &mut keys);
+                assert_eq!(keys, expected);
+            }
+        }
+    }
+
+    // Confirm that sort_slice() matches slice::sort_unstable() on random
+    // keys, for every size up to and somewhat beyond SORT_SLICE_LIMIT, and
+    // that sort_bitonic() sorts every zero-one input of 16 keys.
+    #[test]
+    fn test_sort_slice() {
+        let mut rng = Rng::new(SEED);
+        for n in 0..SORT_SLICE_LIMIT + 8 {
+            for _ in 0..100 {
+                let mut keys: Vec<u64> = (0..n).map(|_| rng.next() % 16).collect();
+                let mut expected = keys.clone();
+                expected.sort_unstable();
+                sort_slice(&mut keys);
+                assert_eq!(keys, expected);
+            }
+        }
+        for mask in 0..1u64 << 16 {
+            let mut keys: Vec<u64> = (0..16).map(|n| (mask >> n) & 1).collect();
+            sort_bitonic(&mut keys);
+            assert!(keys.windows(2).all(|w| w[0] <= w[1]), "mask = {:#x}", mask);
+        }
+    }
+
+    // Confirm the bitonic check for rising-then-falling sequences, rotations
+    // of those sequences, and non-bitonic sequences, then confirm that the
+    // bitonic merge sorts every bitonic zero-one input.
+    #[test]
+    fn test_bitonic() {
+        let yes: [&[u64]; 4] = [&[1,3,5,4,2], &[4,2,1,3,5], &[5,4,2,1,3], &[2,2,2]];
+        let no: [&[u64]; 3] = [&[1,3,2,4], &[3,1,4,2,5], &[0,1,0,1,0,1]];
+        for keys in yes.iter() {assert!(LaneArray::from_keys(keys).is_bitonic(), "{:?}", keys);}
+        for keys in no.iter() {assert!(!LaneArray::from_keys(keys).is_bitonic(), "{:?}", keys);}
+        for mask in 0..256u64 {
+            let x = LaneArray::new(8, &LaneArrayType::Hidden(mask));
+            if x.is_bitonic() {assert!(bitonic_merge(&x).is_sorted_key(), "x = {}", x);}
+        }
+    }
+
+    // Confirm inversion counts for sorted, reversed, and unstable outputs,
+    // and that a network missing one comparator is reported as a near miss.
+    #[test]
+    fn test_inversions() {
+        let sorted = LaneArray::from_keys(&[1, 2, 2, 3]);
+        let reversed = LaneArray::from_keys(&[3, 2, 1, 0]);
+        let mut unstable = LaneArray::from_keys(&[1, 2, 2, 2]);
+        unstable.lanes.reverse();
+        let mut bad = batcher8_net();
+        bad.stages.last_mut().unwrap().pop();
+        let summary = compare_networks(&[("bad", &bad)], bad.len, Metric::Size);
+        assert_eq!((sorted.inversions(), sorted.meta_inversions()), (0, 0));
+        assert_eq!(reversed.inversions(), 6);
+        assert_eq!(unstable.meta_inversions(), 3);
+        assert!(!summary[0].correct);
+        assert_eq!(summary[0].inversions, 1);
+    }
+
+    // Confirm that a single lane-swap with equal keys is stable under the
+    // default tie-break policy and unstable under the opposite policy, and
+    // that the policy never changes the sorted keys.
+    #[test]
+    fn test_tie_break() {
+        let net = batcher8_net();
+        let tie = Network::new(2, vec![vec![sw(0,1)]]);
+        let x = LaneArray::from_keys(&[5, 5]);
+        let lower = tie.apply_with(&x, TieBreak::default());
+        let higher = tie.apply_with(&x, TieBreak::HigherWins);
+        assert!(lower.is_stable() && !higher.is_stable());
+        assert_eq!(x.swap_with(&tie.stages[0], TieBreak::HigherWins).keys(), higher.keys());
+        assert_eq!(lower.lanes[0].payload, tie.apply(&x).lanes[0].payload);
+        for mask in 0..1u64 << net.len {
+            let x = LaneArray::new(net.len, &LaneArrayType::Hidden(mask));
+            assert_eq!(net.apply_with(&x, TieBreak::HigherWins).keys(), net.apply(&x).keys());
+        }
+    }
+
+    // Confirm that permutation networks move input lane perm[i] to output
+    // lane "i" for any input, including the identity and reverse orders,
+    // and that exchanges survive the text format but not deduplication.
+    #[test]
+    fn test_permutation() {
+        let mut rng = Rng::new(SEED);
+        let mut perms: Vec<Vec<usize>> = vec![(0..8).collect(), (0..8).rev().collect()];
+        for n in 1..10usize {
+            let mut perm: Vec<usize> = (0..n).collect();
+            for i in (1..n).rev() {perm.swap(i, rng.next() as usize % (i + 1));}
+            perms.push(perm);
+        }
+        for perm in perms.iter() {
+            let net = permutation_network(perm);
+            for _ in 0..10 {
+                let keys: Vec<u64> = perm.iter().map(|_| rng.next() % 1000).collect();
+                let y = net.apply(&LaneArray::from_keys(&keys));
+                assert_eq!(y.keys(), perm.iter().map(|v| keys[*v]).collect::<Vec<u64>>());
+            }
+            assert!(is_permutation_network(&net));
+            assert!(parse_network(&to_text(&net)).ok().as_ref() == Some(&net));
+        }
+        let swap = permutation_network(&[1, 0]);
+        assert_eq!(swap.apply(&LaneArray::from_keys(&[9, 3])).keys(), vec![3, 9]);
+        assert_eq!(swap.apply(&LaneArray::from_keys(&[3, 9])).keys(), vec![9, 3]);
+        let twice = Network::new(2, vec![swap.stages[0].clone(), swap.stages[0].clone()]);
+        assert_eq!(dedup_comparators(&twice).1, 0);
+        assert_eq!(permutation_network(&perms[0]).size(), 0);
+    }
+
+    // Confirm that the periodic network sorts after log2(n) rounds, but not
+    // with one round fewer.
+    #[test]
+    fn test_periodic() {
+        for k in 1..5usize {
+            let n = 1usize << k;
+            assert!(is_sorting_network(&periodic_network(n, k), None));
+            assert!(!is_sorting_network(&periodic_network(n, k-1), None));
+            assert_eq!(periodic_network(n, k).depth(), k * k);
+        }
+    }
+
+    // Confirm that the lazy test cases cover every mask in both indexing
+    // modes, and that checking them one at a time matches classify().
+    #[test]
+    fn test_lazy() {
+        let net = batcher8_net();
+        let masks: Vec<u64> = test_cases(net.len as usize).map(|(mask, _)| mask).collect();
+        let expected: Vec<u64> = (0..1u64 << net.len).flat_map(|mask| [mask, mask]).collect();
+        let mut bad = net.clone();
+        bad.stages[0].pop();
+        let verdict = |net:&Network| {
+            let (mut err_key, mut err_meta) = (0u64, 0u64);
+            for (_, typ) in test_cases(net.len as usize) {
+                let result = check_case(net, &LaneArray::new(net.len, &typ));
+                if !result.sorted {err_key += 1;}
+                if !result.stable {err_meta += 1;}
+            }
+            Stability::from_errors(err_key, err_meta)
+        };
+        assert_eq!(masks, expected);
+        assert_eq!(verdict(&net), classify(&net, net.len));
+        assert_eq!(verdict(&bad), Stability::Incorrect);
+    }
+
+    // Confirm that a stable network has no failing masks, that an unstable
+    // network fails stability only, and that a broken network fails exactly
+    // the masks found by a brute-force search.
+    #[test]
+    fn test_failing_masks() {
+        let net = batcher8_net();
+        let mut bad = net.clone();
+        bad.stages[0].pop();
+        let brute: Vec<u64> = (0..1u64 << bad.len).filter(|mask| {
+            let types = [LaneArrayType::Simple(*mask),
+                         LaneArrayType::Hidden(*mask)];
+            types.iter().any(|typ| !bad.apply(&LaneArray::new(bad.len, typ)).is_sorted_key())
+        }).collect();
+        let (stable_key, stable_meta) = failing_masks(&transpose8_net(), 8);
+        let (net_key, net_meta) = failing_masks(&net, net.len as usize);
+        let (bad_key, bad_meta) = failing_masks(&bad, bad.len as usize);
+        assert!(stable_key.is_empty() && stable_meta.is_empty());
+        assert!(net_key.is_empty() && !net_meta.is_empty());
+        assert_eq!(bad_key, brute);
+        assert!(bad_meta.iter().all(|mask| !brute.contains(mask)));
+    }
+
+    // Confirm that incremental verification agrees with full verification
+    // while editing each stage of a network in turn, with and without cached
+    // prefixes, and that it falls back for shift networks.
+    #[test]
+    fn test_verify_edit() {
+        let net = batcher8_net();
+        let agrees = |net:&Network, k:usize| verify_after_edit(net, k, net.len as usize)
+            == is_sorting_network(net, None);
+        let mut edit = net.clone();
+        for k in 0..net.depth() {
+            assert!(agrees(&edit, k));
+            let op = edit.stages[k].pop().unwrap();
+            assert!(agrees(&edit, k));
+            edit.stages[k].push(sw(op.1, op.0));
+            assert!(agrees(&edit, k));
+            edit.stages[k] = net.stages[k].clone();
+        }
+        assert!(verify_after_edit(&net, 0, net.len as usize));
+        assert!(agrees(&net, 99));
+        assert!(agrees(&transpose8s_net(), 0));
+        // A large network is verified directly, without caching its states.
+        let entries = PREFIX_CACHE.with(|cache| cache.borrow().len());
+        assert!(!verify_after_edit(&Network::new(32, Vec::new()), 0, 32));
+        assert_eq!(PREFIX_CACHE.with(|cache| cache.borrow().len()), entries);
+    }
+}
@@ -1,335 +1,269 @@
-/// Testing bitonic sorting networks for stability
-/// Copyright 2021 by Alex Utter
-///
-/// This file defines a series of bitonic sorting networks, tests whether
-/// they function correctly, and then tests whether their order remains
-/// stable for tiebreaking purposes.
-///
-/// The motivation is an FPGA design problem described here:
-/// https://www.reddit.com/r/FPGA/comments/qe9j6s/vectorpacking_algorithm/
+//! Command-line tool for testing bitonic sorting networks
+//! Copyright 2021 by Alex Utter
+//!
+//! By default, tests every built-in sorting function for correctness and
+//! stability; subcommands compare, export, and trace individual networks.
 
-use std::cmp;
-use std::fmt;
+// Coding style for this file prefers explicit returns and struct-literal
+// construction; silence the corresponding lints.
+#![allow(clippy::needless_return)]
 
-// Parameters for creating a new Lane or LaneArray object
-// (i.e., Options for how to initialize the key-values for sorting.)
-enum LaneArrayType {
-    Simple(u64),    // Key includes keep/discard mask and lane index
-    Hidden(u64),    // Key includes keep/discard mask only
-}
+use bitonic::*;
+use std::env;
+use std::fs;
+use std::process;
 
-// Each "lane" has a key-value (for sorting) and metadata (for verification).
-#[derive(Clone, Copy)]
-struct Lane {
-    key: u64,
-    meta: u64,
+// Exit with a usage message.
+fn usage(err:&str) -> ! {
+    eprintln!("{}", err);
+    eprintln!("Usage: bitonic [--progress] [--sample N] [--fail-fast] [--dump-unstable]");
+    eprintln!("               [--encoding simple,hidden,max,random]");
+    eprintln!("       bitonic compare [LEN] [--by depth|size|swaps]");
+    eprintln!("       bitonic sort NETWORK KEY...");
+    eprintln!("       bitonic trace NETWORK KEY...");
+    eprintln!("       bitonic trace NETWORK --random [SEED]");
+    eprintln!("       bitonic verify NETWORK|FILE|periodicN [--sample N] [--fail-fast]");
+    eprintln!("       bitonic verify-dir PATH");
+    eprintln!("       bitonic rom NETWORK");
+    eprintln!("       bitonic verilog NETWORK [WIDTH]");
+    eprintln!("       bitonic render NETWORK [--svg|--heatmap|--text]");
+    eprintln!("       bitonic diff NETWORK NETWORK [--svg]");
+    process::exit(1)
 }
 
-// Use a large penalty to increment the keys of disabled lanes.
-const PENALTY:u64 = 256;
-
-impl Lane {
-    // Create a key-value pair based on an index and mask.
-    // Note: Verification data in unused lanes is "don't-care".
-    fn new(typ:&LaneArrayType, idx:u8) -> Lane {
-        let chk = 1u64 << idx;
-        let idx64 = idx as u64;
-        match typ {
-            LaneArrayType::Simple(mask) => {
-                let pen = if mask & chk > 0 {PENALTY} else {0};
-                Lane {key: cmp::max(idx64,pen), meta: cmp::max(idx64,pen)}},
-            LaneArrayType::Hidden(mask) => {
-                let pen = if mask & chk > 0 {PENALTY} else {0};
-                Lane {key: pen, meta: cmp::max(idx64,pen)}},
+// The "compare" subcommand ranks all built-in networks of a given size.
+fn run_compare(args:&[String]) {
+    let mut len = 8u8;
+    let mut by = Metric::Size;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--by" {
+            let name = iter.next().map(|s| s.as_str()).unwrap_or("");
+            by = Metric::parse(name).unwrap_or_else(
+                || usage(&format!("Unknown metric: {}", name)));
+        } else {
+            len = arg.parse().unwrap_or_else(
+                |_| usage(&format!("Unknown argument: {}", arg)));
+            if len as usize > MAX_LANES {
+                usage(&format!("Too many lanes, at most {}.", MAX_LANES))
+            }
         }
     }
+    let builtins = builtin_networks();
+    let nets: Vec<(&str, &Network)> = builtins.iter()
+        .map(|(name, net)| (*name, net)).collect();
+    print_summaries(&compare_networks(&nets, len, by));
 }
 
-// A lane-swap operation is a pair of input/output indices.
-// Order is preserved if #1.key <= #2.key, otherwise swap.
-struct LaneSwap(usize, usize);
-
-fn sw(a:usize, b:usize) -> LaneSwap {
-    LaneSwap {0:a, 1:b}
-}
-
-// An array of lane values, which can be used as an input vector,
-// the state of a pipeline stage, or a vector of outputs.
-#[derive(Clone)]
-struct LaneArray {
-    lanes: Vec<Lane>,
+// Look up a built-in network by name.
+fn find_builtin(name:&str) -> Network {
+    builtin_network(name).unwrap_or_else(|| usage(&format!("Unknown network: {}", name)))
 }
 
-impl LaneArray {
-    // Create a new vector of Lanes of the designated size and type.
-    fn new(len:u8, typ:&LaneArrayType) -> LaneArray {
-        LaneArray {lanes: (0..len).map(|n| Lane::new(typ, n)).collect()}
-    }
-
-    // Are all lanes sorted in ascending order by key?
-    fn is_sorted_key(&self) -> bool {
-        let mut prev = 0u64;
-        for lane in self.lanes.iter() {
-            if lane.key < prev {return false} else {prev = lane.key}
-        }
-        return true
-    }
-
-    // Are all lanes sorted in ascending order by metadata?
-    fn is_sorted_meta(&self) -> bool {
-        let mut prev = 0u64;
-        for lane in self.lanes.iter() {
-            if lane.meta < prev {return false} else {prev = lane.meta}
-        }
-        return true
-    }
-
-    // Apply a series of lane-swap operations to generate a new LaneArray.
-    // Each operator is a pair of input/output indices; smaller key copied
-    // to the first index, larger key to the second.
-    fn swap(&self, ops:&Vec<LaneSwap>) -> LaneArray {
-        let mut result = self.clone();
-        for LaneSwap(n1,n2) in ops.iter() {
-            if self.lanes[*n1].key <= self.lanes[*n2].key {
-                result.lanes[*n1] = self.lanes[*n1].clone();
-                result.lanes[*n2] = self.lanes[*n2].clone();
-            } else {
-                result.lanes[*n1] = self.lanes[*n2].clone();
-                result.lanes[*n2] = self.lanes[*n1].clone();
-            }
-        }
-        return result
-    }
-
-    // Information-deleting analogue to swap() function, shifts up
-    // by replacing any invalid inputs with a constant placeholder.
-    fn shift(&self, ops:&Vec<LaneSwap>) -> LaneArray {
-        let mut result = self.clone();
-        for LaneSwap(n1,n2) in ops.iter() {
-            if self.lanes[*n1].key < PENALTY {
-                result.lanes[*n1] = self.lanes[*n1].clone();
-                result.lanes[*n2] = self.lanes[*n2].clone();
-            } else {
-                result.lanes[*n1] = self.lanes[*n2].clone();
-                result.lanes[*n2] = Lane {key:PENALTY, meta:PENALTY};
-            }
+// Load a network by name: a built-in network, a generated network such as
+// "periodic16" (see periodic_network), or a file in the to_text() format.
+fn load_network(name:&str) -> Network {
+    if let Some(net) = builtin_network(name) {return net}
+    if let Some(n) = name.strip_prefix("periodic").and_then(|n| n.parse::<usize>().ok()) {
+        if !n.is_power_of_two() || n > MAX_LANES {
+            usage(&format!("Invalid periodic network size: {}", n))
         }
-        return result
+        return periodic_network(n, n.trailing_zeros() as usize)
     }
+    let text = fs::read_to_string(name)
+        .unwrap_or_else(|_| usage(&format!("Unknown network: {}", name)));
+    parse_network(&text).unwrap_or_else(|err| usage(&format!("{}: {}", name, err)))
 }
 
-impl fmt::Display for LaneArray {
-    // Print the key values for all lanes.
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "(")?;
-        for lane in self.lanes.iter() {
-            write!(f, "{}, ", &lane.key)?;
-        }
-        write!(f, ")")
-    }
+// The "rom" subcommand prints the schedule ROM for a built-in network.
+fn run_rom(args:&[String]) {
+    let name = args.first().unwrap_or_else(|| usage("Expected network name."));
+    print!("{}", to_verilog_rom(&find_builtin(name), name));
 }
 
-// Given a sorting function, test that it functions correctly
-// and then report whether it preserves order in case of ties.
-fn test_sort(len:u8, lbl:&str, sortfn:fn(&LaneArray)->LaneArray) {
-    // Test that sorting is correct for each possible enable mask,
-    // counting violations in both Simple and Hidden indexing modes.
-    let max_mask = 1u64 << len;
-    let mut err_key = 0u64;
-    let mut err_meta = 0u64;
-    for mask in 0..max_mask {
-        let types = [LaneArrayType::Simple(mask),
-                     LaneArrayType::Hidden(mask)];
-        for typ in types.iter() {
-            let x = LaneArray::new(len, typ);
-            let y = sortfn(&x);
-            if !y.is_sorted_key() {
-                println!("x = {}", x);
-                println!("y = {}", y);
-                err_key += 1;
-            }
-            if !y.is_sorted_meta() {
-                err_meta += 1;
-            }
-        }
-    }
-
-    // Summary report:
-    if err_key > 0 {
-        println!("{}\t Sorting error.", lbl);
-    } else if err_meta > 0 {
-        println!("{}\t Order not preserved.", lbl);
-    } else {
-        println!("{}\t All tests passed.", lbl);
+// The "verilog" subcommand prints a module and testbench for a network.
+fn run_verilog(args:&[String]) {
+    let name = args.first().unwrap_or_else(|| usage("Expected network name."));
+    let width = args.get(1).map_or(16, |arg| arg.parse().unwrap_or_else(
+        |_| usage(&format!("Invalid width: {}", arg))));
+    let net = find_builtin(name);
+    if net.comparators().any(|(_, op)| op.2 == Mode::Shift) && width < SHIFT_WIDTH {
+        usage(&format!("{}: shift operations need a width of at least {} bits.", name, SHIFT_WIDTH))
     }
+    print!("{}", to_verilog(&net, name, width));
+    print!("{}", to_verilog_testbench(&net, name, width));
 }
 
-// Declare functions defining variations on the bitonic sort algorithm.
-// https://en.wikipedia.org/wiki/Bitonic_sorter
-fn bitonic4a(p0:&LaneArray) -> LaneArray {
-    // Bitonic network, original formulation
-    // https://www.inf.hs-flensburg.de/lang/algorithmen/sortieren/bitonic/bitonicen.htm
-    assert_eq!(p0.lanes.len(), 4usize);
-    let p1 = p0.swap(&vec![sw(0,1),sw(3,2)]);
-    let p2 = p1.swap(&vec![sw(0,2),sw(1,3)]);
-    let p3 = p2.swap(&vec![sw(0,1),sw(2,3)]);
-    return p3
+// The "render" subcommand draws a built-in network.
+fn run_render(args:&[String]) {
+    let name = args.first().unwrap_or_else(|| usage("Expected network name."));
+    let net = find_builtin(name);
+    match args.get(1).map(|s| s.as_str()) {
+        None => print!("{}", render_ascii(&net)),
+        Some("--svg") => print!("{}", to_svg(&net)),
+        Some("--heatmap") => print!("{}", to_svg_heatmap(&net)),
+        Some("--text") => print!("{}", to_text(&net)),
+        Some(arg) => usage(&format!("Unknown argument: {}", arg)),
+    }
 }
 
-fn bitonic4b(p0:&LaneArray) -> LaneArray {
-    // Bitonic network, downward swaps only
-    assert_eq!(p0.lanes.len(), 4usize);
-    let p1 = p0.swap(&vec![sw(0,1),sw(2,3)]);
-    let p2 = p1.swap(&vec![sw(0,3),sw(1,2)]);
-    let p3 = p2.swap(&vec![sw(0,1),sw(2,3)]);
-    return p3
+// The "diff" subcommand draws the differences between two networks.
+fn run_diff(args:&[String]) {
+    if args.len() < 2 {usage("Expected two network names.")}
+    let (a, b) = (find_builtin(&args[0]), find_builtin(&args[1]));
+    if a.len != b.len {usage("Networks must have the same number of lanes.")}
+    let d = diff(&a, &b);
+    let overlay = d.overlay(&b);
+    match args.get(2).map(|s| s.as_str()) {
+        None => print!("{}", render_ascii_with(&overlay, |k, op| d.color(k, op))),
+        Some("--svg") => print!("{}", to_svg_with(&overlay,
+            |k, op| d.color(k, op).unwrap_or("black").to_string())),
+        Some(arg) => usage(&format!("Unknown argument: {}", arg)),
+    }
+    println!("{} added, {} removed, {} moved.", d.added.len(), d.removed.len(), d.moved.len());
 }
 
-fn bitonic8a(p0:&LaneArray) -> LaneArray {
-    // Bitonic network, original formulation
-    // https://en.wikipedia.org/wiki/Bitonic_sorter#/media/File:BitonicSort1.svg
-    assert_eq!(p0.lanes.len(), 8usize);
-    let p1 = p0.swap(&vec![sw(0,1),sw(3,2),sw(4,5),sw(7,6)]);
-    let p2 = p1.swap(&vec![sw(0,2),sw(1,3),sw(7,5),sw(6,4)]);
-    let p3 = p2.swap(&vec![sw(0,1),sw(2,3),sw(5,4),sw(7,6)]);
-    let p4 = p3.swap(&vec![sw(0,4),sw(1,5),sw(2,6),sw(3,7)]);
-    let p5 = p4.swap(&vec![sw(0,2),sw(1,3),sw(4,6),sw(5,7)]);
-    let p6 = p5.swap(&vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)]);
-    return p6
+// The "sort" subcommand applies a built-in network to a list of keys.
+fn run_sort(args:&[String]) {
+    let name = args.first().unwrap_or_else(|| usage("Expected network name."));
+    let net = find_builtin(name);
+    let keys: Vec<u64> = args[1..].iter().map(|arg| arg.parse().unwrap_or_else(
+        |_| usage(&format!("Invalid key: {}", arg)))).collect();
+    match net.try_apply(&LaneArray::from_keys(&keys)) {
+        Ok(y) => println!("{}", y),
+        Err(err) => usage(&format!("{}: {}", name, err)),
+    }
 }
 
-fn bitonic8b(p0:&LaneArray) -> LaneArray {
-    // Bitonic network, downward swaps only
-    // https://en.wikipedia.org/wiki/Bitonic_sorter#/media/File:BitonicSort.svg
-    assert_eq!(p0.lanes.len(), 8usize);
-    let p1 = p0.swap(&vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)]);
-    let p2 = p1.swap(&vec![sw(0,3),sw(1,2),sw(4,7),sw(5,6)]);
-    let p3 = p2.swap(&vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)]);
-    let p4 = p3.swap(&vec![sw(0,7),sw(1,6),sw(2,5),sw(3,4)]);
-    let p5 = p4.swap(&vec![sw(0,2),sw(1,3),sw(4,6),sw(5,7)]);
-    let p6 = p5.swap(&vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)]);
-    return p6
-}
+// Number of random inputs tried by "trace --random".
+const TRACE_TRIALS:usize = 10_000;
 
-fn batcher8(p0:&LaneArray) -> LaneArray {
-    // Batcher sort, aka odd-even mergesort
-    // https://www.inf.hs-flensburg.de/lang/algorithmen/sortieren/networks/oemen.htm
-    assert_eq!(p0.lanes.len(), 8usize);
-    let p1 = p0.swap(&vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)]);
-    let p2 = p1.swap(&vec![sw(0,2),sw(1,3),sw(4,6),sw(5,7)]);
-    let p3 = p2.swap(&vec![sw(1,2),sw(5,6)]);
-    let p4 = p3.swap(&vec![sw(0,4),sw(1,5),sw(2,6),sw(3,7)]);
-    let p5 = p4.swap(&vec![sw(2,4),sw(3,5)]);
-    let p6 = p5.swap(&vec![sw(1,2),sw(3,4),sw(5,6)]);
-    return p6
+// Parse a seed in decimal, or in hexadecimal with a "0x" prefix, as it
+// is printed with a counterexample.
+fn parse_seed(arg:&str) -> Option<u64> {
+    match arg.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => arg.parse().ok(),
+    }
 }
 
-fn bubble8(p0:&LaneArray) -> LaneArray {
-    // Bubble sort
-    // https://www.inf.hs-flensburg.de/lang/algorithmen/sortieren/networks/sortieren.htm
-    assert_eq!(p0.lanes.len(), 8usize);
-    let p1 = p0.shift(&vec![sw(0,1)]);
-    let p2 = p1.shift(&vec![sw(1,2)]);
-    let p3 = p2.shift(&vec![sw(0,1),sw(2,3)]);
-    let p4 = p3.shift(&vec![sw(1,2),sw(3,4)]);
-    let p5 = p4.shift(&vec![sw(0,1),sw(2,3),sw(4,5)]);
-    let p6 = p5.shift(&vec![sw(1,2),sw(3,4),sw(5,6)]);
-    let p7 = p6.shift(&vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)]);
-    let p8 = p7.shift(&vec![sw(1,2),sw(3,4),sw(5,6)]);
-    let p9 = p8.shift(&vec![sw(0,1),sw(2,3),sw(4,5)]);
-    let p10 = p9.shift(&vec![sw(1,2),sw(3,4)]);
-    let p11 = p10.shift(&vec![sw(0,1),sw(2,3)]);
-    let p12 = p11.shift(&vec![sw(1,2)]);
-    let p13 = p12.shift(&vec![sw(0,1)]);
-    return p13
+// The "trace" subcommand replays an input through a network,
+// showing the state after every stage.  With "--random", it instead
+// searches for an input that the network fails to sort, and traces that.
+fn run_trace(args:&[String]) {
+    let name = args.first().unwrap_or_else(|| usage("Expected network name."));
+    let net = load_network(name);
+    if args.get(1).map(|s| s.as_str()) == Some("--random") {
+        let seed = args.get(2).map_or(SEED, |arg| parse_seed(arg).unwrap_or_else(
+            || usage(&format!("Invalid seed: {}", arg))));
+        match find_counterexample(&net, seed, TRACE_TRIALS) {
+            Some((seed, keys)) => {
+                println!("Counterexample from seed {:#x}: {:?}", seed, keys);
+                print!("{}", reproduce(&net, &keys));
+            },
+            None => println!("No counterexample in {} trials.", TRACE_TRIALS),
+        }
+        return
+    }
+    let keys: Vec<u64> = args[1..].iter().map(|arg| arg.parse().unwrap_or_else(
+        |_| usage(&format!("Invalid key: {}", arg)))).collect();
+    if keys.len() != net.len as usize {
+        usage(&format!("{}: {}", name, SizeError {expected: net.len as usize, actual: keys.len()}))
+    }
+    print!("{}", reproduce(&net, &keys));
 }
 
-fn pairwise8(p0:&LaneArray) -> LaneArray {
-    // Pairwise sorting network
-    // https://en.wikipedia.org/wiki/Pairwise_sorting_network
-    assert_eq!(p0.lanes.len(), 8usize);
-    let p1 = p0.swap(&vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)]);
-    let p2 = p1.swap(&vec![sw(0,2),sw(1,3),sw(4,6),sw(5,7)]);
-    let p3 = p2.swap(&vec![sw(0,4),sw(1,5),sw(2,6),sw(3,7)]);
-    let p4 = p3.swap(&vec![sw(2,4),sw(3,5)]);
-    let p5 = p4.swap(&vec![sw(1,4),sw(3,6)]);
-    let p6 = p5.swap(&vec![sw(1,2),sw(3,4),sw(5,6)]);
-    return p6
+// The "verify" subcommand tests a single network, which may be loaded or
+// generated as in load_network(), using the same options as the default
+// run (e.g., "--sample N" for networks too large to test exhaustively).
+// Exits with an error if the network does not sort.
+fn run_verify(args:&[String]) {
+    let name = args.first().unwrap_or_else(|| usage("Expected network name."));
+    let net = load_network(name);
+    let opts = TestOptions::parse(&args[1..]).unwrap_or_else(|err| usage(&err));
+    let verdict = test_sort(net.len, name, |x| net.apply(x), &opts);
+    if verdict == Stability::Incorrect {process::exit(1)}
 }
 
-fn transpose8(p0:&LaneArray) -> LaneArray {
-    // Odd-even transpose sort
-    // https://www.inf.hs-flensburg.de/lang/algorithmen/sortieren/networks/oetsen.htm
-    assert_eq!(p0.lanes.len(), 8usize);
-    let p1 = p0.swap(&vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)]);
-    let p2 = p1.swap(&vec![sw(1,2),sw(3,4),sw(5,6)]);
-    let p3 = p2.swap(&vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)]);
-    let p4 = p3.swap(&vec![sw(1,2),sw(3,4),sw(5,6)]);
-    let p5 = p4.swap(&vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)]);
-    let p6 = p5.swap(&vec![sw(1,2),sw(3,4),sw(5,6)]);
-    let p7 = p6.swap(&vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)]);
-    let p8 = p7.swap(&vec![sw(1,2),sw(3,4),sw(5,6)]);
-    return p8
+// The "verify-dir" subcommand loads every network file in a directory,
+// using the format from to_text(), and prints a summary of each.  Exits
+// with an error if any file cannot be parsed or any network is incorrect.
+fn run_verify_dir(args:&[String]) {
+    let path = args.first().unwrap_or_else(|| usage("Expected directory."));
+    let dir = fs::read_dir(path).unwrap_or_else(|err| usage(&format!("{}: {}", path, err)));
+    let mut files: Vec<_> = dir.filter_map(|entry| entry.ok())
+        .map(|entry| entry.path()).filter(|p| p.is_file()).collect();
+    files.sort();
+    let mut summaries = Vec::new();
+    let mut failed = false;
+    for file in files.iter() {
+        let name = file.file_name().unwrap().to_string_lossy().to_string();
+        let text = fs::read_to_string(file).map_err(|err| err.to_string());
+        match text.and_then(|text| parse_network(&text)) {
+            Ok(net) => summaries.extend(compare_networks(&[(&name, &net)], net.len, Metric::Size)),
+            Err(err) => {eprintln!("{}: {}", name, err); failed = true;},
+        }
+    }
+    print_summaries(&summaries);
+    if failed || summaries.iter().any(|s| !s.correct) {process::exit(1)}
 }
 
-fn transpose8s(p0:&LaneArray) -> LaneArray {
-    // Information-deleting analogue to "transpose8".
-    assert_eq!(p0.lanes.len(), 8usize);
-    let p1 = p0.shift(&vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)]);
-    let p2 = p1.shift(&vec![sw(1,2),sw(3,4),sw(5,6)]);
-    let p3 = p2.shift(&vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)]);
-    let p4 = p3.shift(&vec![sw(1,2),sw(3,4),sw(5,6)]);
-    let p5 = p4.shift(&vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)]);
-    let p6 = p5.shift(&vec![sw(1,2),sw(3,4),sw(5,6)]);
-    let p7 = p6.shift(&vec![sw(0,1),sw(2,3),sw(4,5),sw(6,7)]);
-    let p8 = p7.shift(&vec![sw(1,2),sw(3,4),sw(5,6)]);
-    return p8
-}
+// Test each of the defined sorting functions.
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(|s| s.as_str()) {
+        Some("compare") => return run_compare(&args[1..]),
+        Some("sort") => return run_sort(&args[1..]),
+        Some("rom") => return run_rom(&args[1..]),
+        Some("verilog") => return run_verilog(&args[1..]),
+        Some("render") => return run_render(&args[1..]),
+        Some("diff") => return run_diff(&args[1..]),
+        Some("trace") => return run_trace(&args[1..]),
+        Some("verify") => return run_verify(&args[1..]),
+        Some("verify-dir") => return run_verify_dir(&args[1..]),
+        _ => (),
+    }
+    let opts = TestOptions::parse(&args).unwrap_or_else(|err| usage(&err));
+    let verdicts = [
+        test_sort(4, "bitonic4a",   bitonic4a, &opts),
+        test_sort(4, "bitonic4b",   bitonic4b, &opts),
+        test_sort(8, "bitonic8a",   bitonic8a, &opts),
+        test_sort(8, "bitonic8b",   bitonic8b, &opts),
+        test_sort(8, "batcher8",    batcher8, &opts),
+        test_sort(8, "bubble8\t",   bubble8, &opts),
+        test_sort(8, "pairwise8",   pairwise8, &opts),
+        test_sort(8, "transpose8",  transpose8, &opts),
+        test_sort(8, "transpose8s", transpose8s, &opts),
+        test_sort(3, "transpose3s", transpose3s, &opts),
+        test_sort(5, "transpose5s", transpose5s, &opts),
+        test_sort(6, "transpose6s", transpose6s, &opts),
+    ];
+    let zero_one = test_zero_one("zero-one", &opts);
 
-fn transpose3s(p0:&LaneArray) -> LaneArray {
-    // Test variants of "transpose8s" with unusual sizes.
-    assert_eq!(p0.lanes.len(), 3usize);
-    let p1 = p0.shift(&vec![sw(0,1)]);
-    let p2 = p1.shift(&vec![sw(1,2)]);
-    let p3 = p2.shift(&vec![sw(0,1)]);
-    return p3
-}
+    // Optional failure dump for each network that does not preserve order.
+    if opts.dump_unstable {
+        for (lbl, net) in builtin_networks().iter() {
+            dump_unstable(lbl, net);
+        }
+    }
 
-fn transpose5s(p0:&LaneArray) -> LaneArray {
-    // Test variants of "transpose8s" with unusual sizes.
-    assert_eq!(p0.lanes.len(), 5usize);
-    let p1 = p0.shift(&vec![sw(0,1),sw(2,3)]);
-    let p2 = p1.shift(&vec![sw(1,2),sw(3,4)]);
-    let p3 = p2.shift(&vec![sw(0,1),sw(2,3)]);
-    let p4 = p3.shift(&vec![sw(1,2),sw(3,4)]);
-    let p5 = p4.shift(&vec![sw(0,1),sw(2,3)]);
-    return p5
+    // Exit with an error if any sorting function is incorrect.
+    if !zero_one || verdicts.contains(&Stability::Incorrect) {process::exit(1)}
 }
 
-fn transpose6s(p0:&LaneArray) -> LaneArray {
-    // Test variants of "transpose8s" with unusual sizes.
-    assert_eq!(p0.lanes.len(), 6usize);
-    let p1 = p0.shift(&vec![sw(0,1),sw(2,3),sw(4,5)]);
-    let p2 = p1.shift(&vec![sw(1,2),sw(3,4)]);
-    let p3 = p2.shift(&vec![sw(0,1),sw(2,3),sw(4,5)]);
-    let p4 = p3.shift(&vec![sw(1,2),sw(3,4)]);
-    let p5 = p4.shift(&vec![sw(0,1),sw(2,3),sw(4,5)]);
-    let p6 = p5.shift(&vec![sw(1,2),sw(3,4)]);
-    return p6
-}
+// Unit tests for the command-line parsing helpers.
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-// Test each of the defined sorting functions.
-fn main() {
-    test_sort(4, "bitonic4a",   bitonic4a);
-    test_sort(4, "bitonic4b",   bitonic4b);
-    test_sort(8, "bitonic8a",   bitonic8a);
-    test_sort(8, "bitonic8b",   bitonic8b);
-    test_sort(8, "batcher8",    batcher8);
-    test_sort(8, "bubble8\t",   bubble8);
-    test_sort(8, "pairwise8",   pairwise8);
-    test_sort(8, "transpose8",  transpose8);
-    test_sort(8, "transpose8s", transpose8s);
-    test_sort(3, "transpose3s", transpose3s);
-    test_sort(5, "transpose5s", transpose5s);
-    test_sort(6, "transpose6s", transpose6s);
+    // Confirm that a seed round-trips through the format used to print a
+    // counterexample, and that generated networks are loaded by name.
+    #[test]
+    fn test_load() {
+        assert_eq!(parse_seed("0x2545f4914f6cdd1d"), Some(SEED));
+        assert_eq!(parse_seed("42"), Some(42));
+        assert_eq!(parse_seed("0xzz"), None);
+        assert_eq!(load_network("periodic32").len, 32);
+        assert!(load_network("batcher8") == batcher8_net());
+    }
 }